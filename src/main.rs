@@ -11,11 +11,15 @@ use std::{
 };
 
 use derive_more::Unwrap;
-use popups::{AddCard, CardFinder, CatChoice, DependencyStatus};
+use popups::{AddCard, CardFinder, CardReviewer, CatChoice, DependencyStatus, SavedSearch};
 use rodio::{Decoder, OutputStream, Source};
 use sentry::types::Uuid;
+use serde::{Deserialize, Serialize};
 use strum_macros::{EnumIter, EnumString};
 use tabs::{addcards::CardAdder, *};
+use utils::{
+    creation_times_known, front_display, parse_query, prerequisites, DateField, Query, QUERY_HELP,
+};
 
 use browse::Browser;
 use mischef::{App, Retning, Tab, TabData};
@@ -26,13 +30,13 @@ use speki_backend::{
     card::{Card, IsSuspended},
     categories::{Category, CategoryMeta},
     common::current_time,
-    filter::FilterUtil,
     saved_card::SavedCard,
     Id,
 };
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
 mod popups;
+mod settings;
 mod tabs;
 mod utils;
 mod widgets;
@@ -48,10 +52,11 @@ pub type MyTab = dyn Tab<AppState = CardCache, ReturnType = ReturnType>;
 /// The value that each popup can return.
 #[derive(Unwrap, Default, Clone)]
 pub enum ReturnType {
-    Filter(FilterUtil),
+    Filter(Query),
     Card(Id),
     SavedCard(SavedCard),
     Category(Category),
+    Search(SavedSearch),
     #[default]
     NoOp,
 }
@@ -108,6 +113,78 @@ impl CardCache {
     pub fn clear_dependencies(&mut self, id: Id) {
         self.inner.lock().unwrap().clear_dependencies(id);
     }
+
+    /// Changes the card file directly, for the edits the backend doesn't have methods for.
+    pub fn edit_card(&mut self, id: Id, edit: impl FnOnce(&mut Card)) {
+        let card = self.get_owned(id);
+        let path = card.path();
+        let mut card: Card = card.into();
+        edit(&mut card);
+        let toml = toml::to_string(&card).unwrap();
+        std::fs::write(path, toml).unwrap();
+    }
+
+    /// Removes the most recent review from the card's history.
+    pub fn pop_review(&mut self, id: Id) {
+        self.edit_card(id, |card| {
+            card.history.0.pop();
+        });
+    }
+
+    /// The backend can only add tags.
+    pub fn remove_tag(&mut self, id: Id, tag: &str) {
+        self.edit_card(id, |card| {
+            card.meta.tags.remove(tag);
+        });
+    }
+}
+
+/// How often the screen is redrawn when no keys are pressed.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Sets up the terminal for the app, and restores it when dropped. That way it's also
+/// restored when main returns early with an error, or on a panic.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> std::io::Result<Self> {
+        crossterm::terminal::enable_raw_mode()?;
+        // Created before entering the alternate screen, so raw mode is undone if that fails.
+        let guard = Self;
+        crossterm::execute!(
+            std::io::stderr(),
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::cursor::Show
+        )?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        // Nothing more can be done if this fails, the app is closing anyway.
+        crossterm::execute!(std::io::stderr(), crossterm::terminal::LeaveAlternateScreen).ok();
+        crossterm::terminal::disable_raw_mode().ok();
+    }
+}
+
+/// `remynder query <query>` prints the matching cards instead of starting the app. The
+/// words after `query` make up the query, so it doesn't have to be quoted.
+fn print_query(query: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = parse_query(query)?;
+    let cache = CardCache::new();
+    let cards = cache.all_ids();
+    let cards = filter.evaluate_cards(cards, &mut cache.inner.lock().unwrap());
+
+    if filter.uses_date(DateField::Created) && !creation_times_known(&cache) {
+        eprintln!("some cards have no creation time, they never match created filters");
+    }
+
+    for card in cards {
+        println!("{card}\t{}", front_display(&cache.get_ref(card)));
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -125,6 +202,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     std::env::set_var("RUST_BACKTRACE", "1");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some((command, query)) = args.split_first() {
+        if command == "query" {
+            if query.is_empty() {
+                eprintln!("usage: remynder query <query>\n\n{QUERY_HELP}");
+                std::process::exit(1);
+            }
+            return print_query(&query.join(" "));
+        }
+    }
+
     let mut app = {
         let mut cache = CardCache::new();
         //CardCacheInner::reset_serialize();
@@ -147,7 +235,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         App::new(cache, tabs)
     };
 
-    app.run();
+    // Not `app.run()`, as that waits for a key press before drawing again and the countdown
+    // of a timed review has to keep ticking.
+    let _terminal = TerminalGuard::new()?;
+
+    loop {
+        app.draw();
+
+        if crossterm::event::poll(REDRAW_INTERVAL)? && app.handle_key().is_break() {
+            break;
+        }
+    }
 
     Ok(())
 }
@@ -229,7 +327,7 @@ pub fn line_qty(text: &str, area: Rect) -> u16 {
 }
 
 /// Represents a bunch of items getting processed one by one.
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Pipeline<T> {
     pre: Vec<T>,
     current: Option<T>,
@@ -255,6 +353,55 @@ impl<T: Debug> Pipeline<T> {
         self.current = self.pre.pop();
     }
 
+    /// Puts an item back in the queue, so that it comes after `after` other items.
+    pub fn insert(&mut self, item: T, after: usize) {
+        let idx = self.pre.len().saturating_sub(after);
+        self.pre.insert(idx, item);
+    }
+
+    /// Removes the queued occurrence of an item that would come up first.
+    pub fn remove(&mut self, item: &T)
+    where
+        T: PartialEq,
+    {
+        if let Some(idx) = self.pre.iter().rposition(|x| x == item) {
+            self.pre.remove(idx);
+        }
+    }
+
+    /// Drops the items that don't pass, wherever they are in the pipeline.
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        self.pre.retain(&mut keep);
+        self.done.retain(&mut keep);
+        if self.current.as_ref().is_some_and(|current| !keep(current)) {
+            self.current = None;
+        }
+    }
+
+    pub fn is_queued(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.pre.contains(item)
+    }
+
+    pub fn queued_qty(&self) -> usize {
+        self.pre.len()
+    }
+
+    /// Steps back to the most recently finished item, the current one goes back in the queue.
+    pub fn prev(&mut self) {
+        let Some(val) = self.done.pop() else {
+            return;
+        };
+
+        if let Some(current) = self.current.take() {
+            self.pre.push(current);
+        }
+
+        self.current = Some(val);
+    }
+
     pub fn current(&self) -> Option<&T> {
         self.current.as_ref()
     }
@@ -393,6 +540,13 @@ pub trait CardActionTrait: Tab<AppState = CardCache, ReturnType = ReturnType> {
             CardAction::DecrPriority => card.decr_priority(),
             CardAction::IncrPriority => card.incr_priority(),
             CardAction::ClearPriority => card.clear_priority(),
+            CardAction::StudyPrerequisites => {
+                let mut cards = prerequisites(card.id(), cache);
+                // Bottom-up, and the reviewer takes the cards from the back.
+                cards.reverse();
+                let reviewer = CardReviewer::new(cards, cache);
+                self.set_popup(Box::new(reviewer));
+            }
             CardAction::Menu => {}
         }
     }
@@ -424,6 +578,7 @@ pub enum CardAction {
     ReverseDependency,
     ClearDependencies,
     ChangeCategory,
+    StudyPrerequisites,
 }
 
 impl CardAction {
@@ -444,9 +599,23 @@ impl CardAction {
             "p" => Ok(Self::DecrPriority),
             "P" => Ok(Self::IncrPriority),
             "z" => Ok(Self::ChangeCategory),
+            "v" => Ok(Self::StudyPrerequisites),
             _ => Err(()),
         }
     }
+
+    /// Whether the action opens a popup of its own, such as a card finder.
+    pub fn opens_popup(&self) -> bool {
+        matches!(
+            self,
+            Self::NewDependent
+                | Self::NewDependency
+                | Self::OldDependent
+                | Self::OldDependency
+                | Self::ChangeCategory
+                | Self::StudyPrerequisites
+        )
+    }
 }
 
 pub fn play_audio(file_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
@@ -469,3 +638,59 @@ pub fn play_audio(file_path: PathBuf) -> Result<(), Box<dyn std::error::Error>>
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_prev() {
+        let mut pipeline = Pipeline::new(vec![3, 2, 1]);
+        pipeline.next();
+        pipeline.next();
+        assert_eq!(pipeline.current(), Some(&2));
+
+        pipeline.prev();
+        assert_eq!(pipeline.current(), Some(&1));
+        assert_eq!(pipeline.progress(), (0, 3));
+
+        pipeline.next();
+        assert_eq!(pipeline.current(), Some(&2));
+
+        pipeline.prev();
+        pipeline.prev();
+        assert_eq!(pipeline.current(), Some(&1));
+    }
+
+    #[test]
+    fn pipeline_insert() {
+        let mut pipeline = Pipeline::new(vec![4, 3, 2, 1]);
+        pipeline.next();
+        pipeline.insert(1, 2);
+        assert_eq!(pipeline.queued_qty(), 4);
+
+        pipeline.next();
+        pipeline.next();
+        pipeline.next();
+        assert_eq!(pipeline.current(), Some(&1));
+
+        pipeline.insert(1, 10);
+        pipeline.remove(&1);
+        pipeline.next();
+        pipeline.next();
+        assert!(pipeline.is_done());
+    }
+
+    #[test]
+    fn pipeline_retain() {
+        let mut pipeline = Pipeline::new(vec![4, 3, 2, 1]);
+        pipeline.next();
+        pipeline.next();
+        pipeline.retain(|item| item % 2 == 1);
+        assert_eq!(pipeline.current(), None);
+        assert_eq!(pipeline.progress(), (1, 2));
+
+        pipeline.next();
+        assert_eq!(pipeline.current(), Some(&3));
+    }
+}
@@ -1,13 +1,13 @@
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::prelude::Rect;
-use speki_backend::{card::Card, categories::Category, Id};
+use speki_backend::{card::Card, categories::Category, saved_card::SavedCard, Id};
 
 use mischef::{Tab, TabData, Widget};
 
 use crate::{
     popups::CatChoice,
     split_off,
-    utils::{TextDisplay, TextInput},
+    utils::{cloze_answer, cloze_numbers, cloze_tag, note_tag, TextDisplay, TextInput},
     vsplit2, CardCache, MyTabData, ReturnType,
 };
 
@@ -26,6 +26,8 @@ pub struct AddCard<'a> {
     tabdata: MyTabData,
     dependency: Option<DependencyStatus>,
     message: String,
+    /// Added to the new cards.
+    tags: Vec<String>,
 }
 
 impl<'a> AddCard<'a> {
@@ -44,11 +46,28 @@ impl<'a> AddCard<'a> {
         s
     }
 
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
     fn refresh(&mut self) {
         self.status_bar.text = format!("{}    {}", self.message, self.category.print_full());
     }
 }
 
+fn save_card(
+    front: String,
+    back: String,
+    finished: bool,
+    category: &Category,
+    cache: &mut CardCache,
+) -> SavedCard {
+    let mut card = Card::new_simple(front, back);
+    card.finished = finished;
+    card.save_new_card(category, &mut cache.inner.lock().unwrap())
+}
+
 fn split_area(area: Rect) -> (Rect, Rect, Rect) {
     let (status, area) = split_off(area, 1, crate::Retning::Up);
     let (front, back) = vsplit2(area, 50, 50);
@@ -108,28 +127,59 @@ impl Tab for AddCard<'_> {
             || key.code == KeyCode::Enter
         {
             let dependency = self.dependency.clone();
+            let tags = self.tags.clone();
             let old_self = std::mem::take(self);
             self.category = old_self.category;
             self.refresh();
 
-            let mut card = Card::new_simple(
-                old_self.front.text.into_lines().join("\n"),
-                old_self.back.text.into_lines().join("\n"),
-            );
+            let front = old_self.front.text.into_lines().join("\n");
+            let back = old_self.back.text.into_lines().join("\n");
 
-            if card.front.is_empty() {
+            if front.is_empty() {
                 return false;
             };
 
-            card.finished = key.code == KeyCode::Enter;
-            let card = card.save_new_card(&self.category, &mut cache.inner.lock().unwrap());
-
-            match dependency {
-                Some(DependencyStatus::Dependency(id)) => cache.set_dependency(card.id(), id),
-                Some(DependencyStatus::Dependent(id)) => cache.set_dependency(id, card.id()),
-                None => {}
-            };
-
+            let finished = key.code == KeyCode::Enter;
+            let clozes = cloze_numbers(&front);
+            let mut cards = vec![];
+
+            if clozes.is_empty() {
+                cards.push(save_card(front, back, finished, &self.category, cache));
+            } else {
+                // Each cloze number becomes its own card, with the whole note as the front.
+                for number in clozes {
+                    let mut answer = cloze_answer(&front, number);
+                    if !back.is_empty() {
+                        answer = format!("{answer}\n\n{back}");
+                    }
+
+                    let mut card =
+                        save_card(front.clone(), answer, finished, &self.category, cache);
+                    card.insert_tag(cloze_tag(number));
+                    cards.push(card);
+                }
+
+                // The clozes of a note are siblings, none of them is a dependency of another,
+                // so they're only tied together by a tag.
+                let note = note_tag(cards[0].id());
+                for card in &mut cards {
+                    card.insert_tag(note.clone());
+                }
+            }
+
+            for card in &mut cards {
+                for tag in &tags {
+                    card.insert_tag(tag.clone());
+                }
+
+                match dependency {
+                    Some(DependencyStatus::Dependency(id)) => cache.set_dependency(card.id(), id),
+                    Some(DependencyStatus::Dependent(id)) => cache.set_dependency(id, card.id()),
+                    None => {}
+                };
+            }
+
+            let card = cards.remove(0);
             self.resolve_tab(ReturnType::SavedCard(card));
 
             return false;
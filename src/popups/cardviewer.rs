@@ -2,14 +2,15 @@ use speki_backend::Id;
 
 use crate::{
     hsplit2,
+    tabs::review::CurrentCard,
     utils::{TextInput, TreeWidget},
-    vsplit2, MyTabData, ReturnType,
+    vsplit2, CardAction, CardActionTrait, MyTabData, ReturnType,
 };
 
 use super::*;
 
 pub struct CardInspector<'a> {
-    _card: Id,
+    card: Id,
     front: TextInput<'a>,
     back: TextInput<'a>,
     dependencies: TreeWidget<'a, Id>,
@@ -24,7 +25,7 @@ impl CardInspector<'_> {
         let b = TextInput::new(card.back_text().to_string());
 
         Self {
-            _card: card_id,
+            card: card_id,
             front: f,
             back: b,
             tab_data: TabData::default(),
@@ -34,6 +35,14 @@ impl CardInspector<'_> {
     }
 }
 
+impl CurrentCard for CardInspector<'_> {
+    fn selected_card(&self) -> Option<Id> {
+        Some(self.card)
+    }
+}
+
+impl CardActionTrait for CardInspector<'_> {}
+
 impl<'a> Tab for CardInspector<'a> {
     type AppState = CardCache;
     type ReturnType = ReturnType;
@@ -62,6 +71,19 @@ impl<'a> Tab for CardInspector<'a> {
         ]
     }
 
+    fn tab_keyhandler_deselected(
+        &mut self,
+        cache: &mut Self::AppState,
+        key: crossterm::event::KeyEvent,
+    ) -> bool {
+        if key.code == KeyCode::Char('v') {
+            self.evaluate(self.card, cache, CardAction::StudyPrerequisites);
+            return false;
+        }
+
+        true
+    }
+
     fn tabdata(&mut self) -> &mut TabData<Self::AppState, Self::ReturnType> {
         &mut self.tab_data
     }
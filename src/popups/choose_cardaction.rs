@@ -1,14 +1,19 @@
 use crossterm::event::KeyCode;
 use mischef::{Tab, TabData};
 use speki_backend::Id;
+use strum::IntoEnumIterator;
 
 use crate::{
-    widgets::enum_choice::EnumChoice, CardAction, CardActionTrait, CardCache, MyTabData, ReturnType,
+    split_off, utils::TextDisplay, widgets::enum_choice::EnumChoice, CardAction, CardActionTrait,
+    CardCache, MyTabData, ReturnType,
 };
 
 pub struct ActionPicker {
     cards: Vec<Id>,
     choice: EnumChoice<CardAction>,
+    /// Actions on more than one card have to be confirmed.
+    confirm: Option<CardAction>,
+    status: TextDisplay,
     tab_data: MyTabData,
 }
 
@@ -16,12 +21,54 @@ impl CardActionTrait for ActionPicker {}
 
 impl ActionPicker {
     pub fn new(cards: Vec<Id>) -> Self {
+        let status = TextDisplay::new(format!("{} cards", cards.len()));
+        // Their popups would be closed along with the picker.
+        let choice = CardAction::iter()
+            .filter(CardAction::opens_popup)
+            .fold(EnumChoice::new(), EnumChoice::without);
         Self {
             cards,
-            choice: EnumChoice::new(),
+            choice,
+            confirm: None,
+            status,
             tab_data: TabData::default(),
         }
     }
+
+    /// Skips picking the action, only asks for the confirmation.
+    pub fn confirm(cards: Vec<Id>, action: CardAction) -> Self {
+        let mut picker = Self::new(cards);
+        picker.ask_confirmation(action);
+        picker
+    }
+
+    fn ask_confirmation(&mut self, action: CardAction) {
+        self.status = TextDisplay::new(format!("{action} on {} cards? (y/n)", self.cards.len()));
+        self.confirm = Some(action);
+    }
+
+    /// Takes y/n while there's an action to confirm. Returns whether the key was used.
+    fn answer_confirmation(&mut self, cache: &mut CardCache, key: KeyCode) -> bool {
+        let Some(action) = self.confirm else {
+            return false;
+        };
+
+        match key {
+            KeyCode::Char('y') => self.apply(cache, action),
+            KeyCode::Char('n') => self.exit_tab(),
+            _ => {}
+        }
+        true
+    }
+
+    fn apply(&mut self, cache: &mut CardCache, action: CardAction) {
+        let cards = self.cards.clone();
+        for card in cards {
+            self.evaluate(card, cache, action);
+        }
+        // Lets the browser refresh its list, the cards might be gone or filtered out now.
+        self.resolve_tab(ReturnType::NoOp);
+    }
 }
 
 impl AsRef<MyTabData> for ActionPicker {
@@ -47,7 +94,8 @@ impl Tab for ActionPicker {
         &mut dyn mischef::Widget<AppData = Self::AppState>,
         ratatui::prelude::Rect,
     )> {
-        vec![(&mut self.choice, area)]
+        let (status, choice) = split_off(area, 1, mischef::Retning::Up);
+        vec![(&mut self.status, status), (&mut self.choice, choice)]
     }
 
     fn tab_keyhandler_selected(
@@ -55,18 +103,31 @@ impl Tab for ActionPicker {
         cache: &mut Self::AppState,
         key: crossterm::event::KeyEvent,
     ) -> bool {
+        if self.answer_confirmation(cache, key.code) {
+            return false;
+        }
+
         if key.code == KeyCode::Enter {
             let action = self.choice.current_item();
-            let cards = self.cards.clone();
-            for card in cards {
-                self.evaluate(card, cache, action);
+            if self.cards.len() > 1 {
+                self.ask_confirmation(action);
+            } else {
+                self.apply(cache, action);
             }
-            self.exit_tab();
             return false;
         }
         true
     }
 
+    // The picker isn't selected when it's opened only to confirm an action.
+    fn tab_keyhandler_deselected(
+        &mut self,
+        cache: &mut Self::AppState,
+        key: crossterm::event::KeyEvent,
+    ) -> bool {
+        !self.answer_confirmation(cache, key.code)
+    }
+
     fn tabdata(&mut self) -> &mut TabData<Self::AppState, Self::ReturnType> {
         self.as_mut()
     }
@@ -1,11 +1,18 @@
 use speki_backend::Id;
 
-use crate::{hsplit2, utils::StatefulList, MyTabData, ReturnType};
+use crate::{
+    hsplit2, split_off,
+    utils::{parse_query, StatefulList, TextDisplay, TextInput, QUERY_HELP},
+    MyTabData, ReturnType,
+};
 
 use super::*;
 
 pub struct FilterChoice<'a> {
     filter: InputTable<'a, FilterUtil>,
+    /// For the filters the table can't express, like `a or b` and the dependency sub-filters.
+    query: TextInput<'a>,
+    status: TextDisplay,
     list: StatefulList<Id>,
     tabdata: MyTabData,
 }
@@ -18,6 +25,8 @@ impl<'a> FilterChoice<'a> {
         Self {
             list,
             filter: InputTable::new(),
+            query: TextInput::default(),
+            status: TextDisplay::new(QUERY_HELP.to_string()),
             tabdata: TabData::default(),
         }
     }
@@ -36,12 +45,20 @@ impl Tab for FilterChoice<'_> {
         _cache: &mut Self::AppState,
         key: crossterm::event::KeyEvent,
     ) -> bool {
-        if self.is_selected(&self.filter)
-            && self.tabdata.is_selected
-            && key.code == KeyCode::Enter
-            && self.filter.is_valid()
-        {
-            self.resolve_tab(ReturnType::Filter(self.filter.extract_type()))
+        if !self.tabdata.is_selected || key.code != KeyCode::Enter {
+            return true;
+        }
+
+        if self.is_selected(&self.query) {
+            match parse_query(&self.query.get_text()) {
+                Ok(query) => self.resolve_tab(ReturnType::Filter(query)),
+                Err(e) => self.status = TextDisplay::new(format!("{e}\n\n{QUERY_HELP}")),
+            }
+            return false;
+        }
+
+        if self.is_selected(&self.filter) && self.filter.is_valid() {
+            self.resolve_tab(ReturnType::Filter(self.filter.extract_type().into()))
         }
         true
     }
@@ -54,7 +71,14 @@ impl Tab for FilterChoice<'_> {
         ratatui::prelude::Rect,
     )> {
         let (list, filter) = hsplit2(area, 50, 50);
-        vec![(&mut self.filter, filter), (&mut self.list, list)]
+        let (query, filter) = split_off(filter, 3, mischef::Retning::Up);
+        let (filter, status) = split_off(filter, 6, mischef::Retning::Down);
+        vec![
+            (&mut self.query, query),
+            (&mut self.filter, filter),
+            (&mut self.status, status),
+            (&mut self.list, list),
+        ]
     }
 
     fn tabdata(&mut self) -> &mut mischef::TabData<Self::AppState, Self::ReturnType> {
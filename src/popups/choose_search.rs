@@ -0,0 +1,193 @@
+use crossterm::event::KeyCode;
+use mischef::{Tab, TabData, Widget};
+use serde::{Deserialize, Serialize};
+use vedvaring::SingletonPersist;
+
+use crate::{
+    split_off,
+    tabs::browse::Sorter,
+    utils::{Query, StatefulList, TextDisplay, TextInput},
+    CardCache, MyTabData, ReturnType,
+};
+
+/// A filter of the browser, with its sort order, saved under a name.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: Query,
+    pub sort_by: Option<Sorter>,
+    pub then_by: Option<Sorter>,
+    pub descending: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct SavedSearches {
+    pub searches: Vec<SavedSearch>,
+}
+
+impl SavedSearches {
+    /// Replaces the search that has the same name, if there is one.
+    pub fn insert(search: SavedSearch) {
+        let mut saved = Self::load();
+        saved.searches.retain(|other| other.name != search.name);
+        saved.searches.push(search);
+        saved.searches.sort_by(|a, b| a.name.cmp(&b.name));
+        saved.save();
+    }
+
+    pub fn remove(name: &str) {
+        let mut saved = Self::load();
+        saved.searches.retain(|other| other.name != name);
+        saved.save();
+    }
+
+    pub fn get(name: &str) -> Option<SavedSearch> {
+        Self::load()
+            .searches
+            .into_iter()
+            .find(|saved| saved.name == name)
+    }
+
+    fn names() -> Vec<String> {
+        Self::load()
+            .searches
+            .into_iter()
+            .map(|saved| saved.name)
+            .collect()
+    }
+}
+
+impl SingletonPersist for SavedSearches {
+    fn name() -> String {
+        "saved_searches".into()
+    }
+
+    fn dir_name() -> String {
+        "speki".into()
+    }
+}
+
+/// Lists the saved searches, and saves the current one of the browser if it has one.
+pub struct SearchChoice<'a> {
+    list: StatefulList<String>,
+    /// The name to save `unsaved` under.
+    name: TextInput<'a>,
+    unsaved: Option<SavedSearch>,
+    status: TextDisplay,
+    tabdata: MyTabData,
+}
+
+impl SearchChoice<'_> {
+    pub fn new(unsaved: Option<SavedSearch>) -> Self {
+        let status = if unsaved.is_some() {
+            "type a name and press enter to save the current search\n"
+        } else {
+            ""
+        };
+
+        Self {
+            list: StatefulList::with_items(SavedSearches::names()),
+            name: TextInput::default(),
+            unsaved,
+            status: TextDisplay::new(format!("{status}enter: use  x: delete")),
+            tabdata: TabData::default(),
+        }
+    }
+
+    fn save(&mut self) {
+        let name = self.name.get_text().trim().to_string();
+        let Some(unsaved) = self.unsaved.clone() else {
+            return;
+        };
+
+        if name.is_empty() {
+            self.status = TextDisplay::new("the search needs a name".into());
+            return;
+        }
+
+        SavedSearches::insert(SavedSearch {
+            name: name.clone(),
+            ..unsaved
+        });
+        self.list = StatefulList::with_items(SavedSearches::names());
+        self.name.clear();
+        self.status = TextDisplay::new(format!("saved '{name}'\nenter: use  x: delete"));
+    }
+}
+
+impl Tab for SearchChoice<'_> {
+    type AppState = CardCache;
+    type ReturnType = ReturnType;
+
+    fn tabdata_ref(&self) -> &TabData<Self::AppState, Self::ReturnType> {
+        &self.tabdata
+    }
+
+    fn tab_keyhandler(
+        &mut self,
+        _cache: &mut Self::AppState,
+        key: crossterm::event::KeyEvent,
+    ) -> bool {
+        if !self.tabdata.is_selected {
+            return true;
+        }
+
+        if self.is_selected(&self.name) && key.code == KeyCode::Enter {
+            self.save();
+            return false;
+        }
+
+        if self.is_selected(&self.list) {
+            let Some(name) = self.list.selected().cloned() else {
+                return true;
+            };
+
+            match key.code {
+                KeyCode::Enter => {
+                    if let Some(search) = SavedSearches::get(&name) {
+                        self.resolve_tab(ReturnType::Search(search));
+                    }
+                }
+                KeyCode::Char('x') => {
+                    SavedSearches::remove(&name);
+                    self.list = StatefulList::with_items(SavedSearches::names());
+                }
+                _ => return true,
+            }
+
+            return false;
+        }
+
+        true
+    }
+
+    fn widgets(
+        &mut self,
+        area: ratatui::prelude::Rect,
+    ) -> Vec<(
+        &mut dyn Widget<AppData = Self::AppState>,
+        ratatui::prelude::Rect,
+    )> {
+        let (list, status) = split_off(area, 4, mischef::Retning::Down);
+
+        if self.unsaved.is_some() {
+            let (name, list) = split_off(list, 3, mischef::Retning::Up);
+            vec![
+                (&mut self.name, name),
+                (&mut self.list, list),
+                (&mut self.status, status),
+            ]
+        } else {
+            vec![(&mut self.list, list), (&mut self.status, status)]
+        }
+    }
+
+    fn tabdata(&mut self) -> &mut mischef::TabData<Self::AppState, Self::ReturnType> {
+        &mut self.tabdata
+    }
+
+    fn title(&self) -> &str {
+        "saved searches"
+    }
+}
@@ -14,6 +14,9 @@ pub use add_card::*;
 mod choose_filter;
 pub use choose_filter::*;
 
+mod choose_search;
+pub use choose_search::*;
+
 mod cardviewer;
 pub use cardviewer::*;
 
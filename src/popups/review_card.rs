@@ -1,19 +1,300 @@
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
 
 use crossterm::event::KeyCode;
-use speki_backend::{common::current_time, review::Grade, Id};
+use serde::{Deserialize, Serialize};
+use speki_backend::{
+    card::IsSuspended, common::current_time, review::Grade, saved_card::SavedCard, Id,
+};
 
 use mischef::{Retning, Tab, TabData, Widget};
+use vedvaring::SingletonPersist;
 
 use ratatui::prelude::*;
 
 use crate::{
-    hsplit2, split_off,
+    hsplit2,
+    popups::{AddCard, DependencyStatus},
+    settings::{DailyCount, Settings},
+    split_off,
     tabs::review::CurrentCard,
-    utils::{card_dependencies, card_dependents, TextDisplay, TextInput, TreeWidget},
+    utils::{
+        active_cloze, card_dependencies, card_dependents, char_diff, cloze_answer, front_display,
+        mark_leech, similarity, unmark_leech, DiffDisplay, TextDisplay, TextInput, TreeWidget,
+    },
     vsplit2, CardAction, CardActionTrait, CardCache, MyTabData, Pipeline, ReturnType,
 };
 
+/// Cards made for a missing dependency of a failed card are tagged with this.
+pub const GAP_TAG: &str = "gap";
+
+/// A review session that hasn't been finished, saved so that it can be resumed later.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ReviewSession {
+    pub cards: Pipeline<Id>,
+    pub relearning: BTreeSet<Id>,
+    /// Failed cards that were waiting for their relearn delay.
+    pub delayed: Vec<Id>,
+    pub cram: bool,
+    pub introduced: BTreeSet<Id>,
+}
+
+impl ReviewSession {
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_done() && self.delayed.is_empty()
+    }
+
+    pub fn unfinished_qty(&self) -> usize {
+        self.cards.unfinished_qty() + self.delayed.len()
+    }
+}
+
+impl SingletonPersist for ReviewSession {
+    fn name() -> String {
+        "session".into()
+    }
+
+    fn dir_name() -> String {
+        "speki".into()
+    }
+}
+
+/// Grades from cram sessions. They're kept out of the review history of the cards, so that
+/// cramming doesn't skew the stability and recall rate estimates.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct PracticeLog {
+    pub entries: Vec<Practice>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Practice {
+    pub card: Id,
+    pub grade: Grade,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    pub time_spent_secs: f32,
+}
+
+impl PracticeLog {
+    pub fn record(card: Id, grade: Grade, time_spent: Duration) {
+        let mut log = Self::load();
+        log.entries.push(Practice {
+            card,
+            grade,
+            timestamp: current_time().as_secs(),
+            time_spent_secs: time_spent.as_secs_f32(),
+        });
+        log.save();
+    }
+
+    /// For when a practice gets undone.
+    pub fn pop() {
+        let mut log = Self::load();
+        log.entries.pop();
+        log.save();
+    }
+}
+
+impl SingletonPersist for PracticeLog {
+    fn name() -> String {
+        "practice_log".into()
+    }
+
+    fn dir_name() -> String {
+        "speki".into()
+    }
+}
+
+/// Cards that were skipped in a review. Cards that keep getting skipped are usually badly
+/// written.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct SkipLog {
+    pub entries: Vec<Skip>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Skip {
+    pub card: Id,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// Suspended until the next day instead of pushed back in the session.
+    pub buried: bool,
+}
+
+impl SkipLog {
+    pub fn record(card: Id, buried: bool) {
+        let mut log = Self::load();
+        log.entries.push(Skip {
+            card,
+            timestamp: current_time().as_secs(),
+            buried,
+        });
+        log.save();
+    }
+
+    /// For when a skip gets undone.
+    pub fn pop() {
+        let mut log = Self::load();
+        log.entries.pop();
+        log.save();
+    }
+
+    /// How many times each card has been skipped.
+    pub fn counts(&self) -> BTreeMap<Id, usize> {
+        let mut counts = BTreeMap::new();
+        for skip in &self.entries {
+            *counts.entry(skip.card).or_default() += 1;
+        }
+        counts
+    }
+}
+
+impl SingletonPersist for SkipLog {
+    fn name() -> String {
+        "skip_log".into()
+    }
+
+    fn dir_name() -> String {
+        "speki".into()
+    }
+}
+
+/// How the time of each review was split up. The review history of a card only has the
+/// total time, from showing the front until grading.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct RecallLog {
+    pub entries: Vec<Recall>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Recall {
+    pub card: Id,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// From showing the front until the back was revealed.
+    pub recall_secs: f32,
+    /// From revealing the back until grading.
+    pub grading_secs: f32,
+}
+
+impl RecallLog {
+    pub fn record(card: Id, recall_time: Duration, grading_time: Duration) {
+        let mut log = Self::load();
+        log.entries.push(Recall {
+            card,
+            timestamp: current_time().as_secs(),
+            recall_secs: recall_time.as_secs_f32(),
+            grading_secs: grading_time.as_secs_f32(),
+        });
+        log.save();
+    }
+
+    /// For when a review gets undone.
+    pub fn pop() {
+        let mut log = Self::load();
+        log.entries.pop();
+        log.save();
+    }
+
+    /// The most recent recall of each card.
+    pub fn latest(&self) -> BTreeMap<Id, Recall> {
+        self.entries
+            .iter()
+            .map(|recall| (recall.card, recall.clone()))
+            .collect()
+    }
+}
+
+impl SingletonPersist for RecallLog {
+    fn name() -> String {
+        "recall_log".into()
+    }
+
+    fn dir_name() -> String {
+        "speki".into()
+    }
+}
+
+/// A card the review session has moved past, and the grade it got if it wasn't skipped.
+#[derive(Debug, Clone)]
+struct SessionEntry {
+    card: Id,
+    grade: Option<Grade>,
+    /// Whether the card had already been failed earlier in the session.
+    relearn: bool,
+    /// Whether this was the first review of the card.
+    was_new: bool,
+    /// Whether the card became a leech from this review.
+    leech: bool,
+    /// Dependents of the card that were taken out of the session because it was failed.
+    postponed: Vec<Id>,
+    /// A new card that was shown with its answer, to be tested later in the session.
+    studied: bool,
+    /// Skipped and suspended until the next day, rather than pushed back in the session.
+    buried: bool,
+    /// Skipped and taken out of the session, as it can't be graded.
+    dropped: bool,
+    /// From showing the front until the card was graded or skipped.
+    time_spent: Duration,
+    /// From showing the front until the back was revealed.
+    recall_time: Option<Duration>,
+}
+
+impl SessionEntry {
+    fn is_failed(&self) -> bool {
+        self.grade
+            .as_ref()
+            .is_some_and(|grade| !grade.is_succesful_recall())
+    }
+
+    /// A leech that got suspended shouldn't come back in this session either.
+    fn is_requeued(&self, settings: &Settings) -> bool {
+        self.is_failed() && !(self.leech && settings.suspend_leeches)
+    }
+
+    fn is_skipped(&self) -> bool {
+        self.grade.is_none() && !self.studied
+    }
+
+    /// Skipped cards are shown again at the end of the session, unless they're buried or
+    /// dropped.
+    fn is_pushed_back(&self) -> bool {
+        self.is_skipped() && !self.buried && !self.dropped
+    }
+
+    /// Studied and skipped cards are put back in the session, so they'd be counted twice.
+    fn is_added_back(&self) -> bool {
+        self.studied || self.is_pushed_back()
+    }
+}
+
+/// Progress of the session as `(done, total)` and `(relearned, requeued)`, where `finished`
+/// and `total` are what the queue has had in it. Each entry is counted once, so a skipped
+/// re-review is added back but isn't a re-review.
+fn progress(
+    history: &[SessionEntry],
+    finished: usize,
+    total: usize,
+    settings: &Settings,
+) -> ((usize, usize), (usize, usize)) {
+    let requeued = history.iter().filter(|e| e.is_requeued(settings)).count();
+    let added_back = history.iter().filter(|e| e.is_added_back()).count();
+    let relearned = history
+        .iter()
+        .filter(|e| e.relearn && !e.is_added_back())
+        .count();
+
+    let total = total - requeued - added_back;
+    let done = finished - relearned - added_back;
+    ((done, total), (relearned, requeued))
+}
+
 pub struct CardReviewer<'a> {
     pub cards: Pipeline<Id>,
     pub dependencies: TreeWidget<'a, Id>,
@@ -23,12 +304,84 @@ pub struct CardReviewer<'a> {
     pub card_info: TextDisplay,
     pub info: TextDisplay,
     pub tab_data: MyTabData,
+    /// When the front of the current card was shown.
+    card_shown: Instant,
+    /// How long it took from showing the front until the back was revealed.
+    recall_time: Option<Duration>,
+    history: Vec<SessionEntry>,
+    /// Cards that have been failed in this session and not yet passed.
+    relearning: BTreeSet<Id>,
+    /// Failed cards waiting for their relearn delay to run out.
+    delayed: Vec<(Id, Instant)>,
+    /// New cards that have been studied, so the next time they're shown they're tested.
+    introduced: BTreeSet<Id>,
+    /// The current card is new, so it's shown with the answer instead of being tested.
+    studying: bool,
+    /// How long there is to recall the current card.
+    time_limit: Option<Duration>,
+    settings: Settings,
+    session_start: Instant,
+    summary: TextDisplay,
+    /// Where the answer is typed in when `type_answers` is set.
+    answer: TextInput<'a>,
+    answer_diff: DiffDisplay,
+    suggested_grade: Option<Grade>,
+    /// A new card is shown, so the answer input should get the focus on the next render.
+    focus_answer: bool,
+    /// Drill the cards without touching their review history, grades go to the
+    /// `PracticeLog` instead.
+    cram: bool,
+    /// Whether the session is saved to disk so it can be resumed. Only sessions started
+    /// from the review menu are, so a short one from elsewhere doesn't overwrite them.
+    persist: bool,
+    /// Enter was pressed with nothing selected. mischef uses that to select the widget
+    /// without passing the key on, so it's handled in `after_keyhandler`.
+    enter_pressed: bool,
 }
 
 impl CardReviewer<'_> {
     pub fn new(cards: Vec<Id>, cache: &mut CardCache) -> Self {
+        Self::with_pipeline(Pipeline::new(cards), false, false, cache)
+    }
+
+    /// A session that doesn't affect the scheduling. Failed cards still come back until
+    /// they're passed.
+    pub fn cram(cards: Vec<Id>, cache: &mut CardCache) -> Self {
+        Self::with_pipeline(Pipeline::new(cards), true, false, cache)
+    }
+
+    /// A session that's saved to disk as it goes, replacing the one that was saved before.
+    pub fn saved(cards: Vec<Id>, cram: bool, cache: &mut CardCache) -> Self {
+        Self::with_pipeline(Pipeline::new(cards), cram, true, cache)
+    }
+
+    /// Continues a session that was saved to disk. Cards that were deleted since then are
+    /// left out.
+    pub fn resume(mut session: ReviewSession, cache: &mut CardCache) -> Self {
+        let exists = |card: &Id| cache.try_get_ref(*card).is_some();
+        session.cards.retain(exists);
+        session.delayed.retain(exists);
+        session.relearning.retain(exists);
+        session.introduced.retain(exists);
+
+        let mut myself = Self::with_pipeline(session.cards, session.cram, true, cache);
+        myself.relearning = session.relearning;
+        myself.introduced = session.introduced;
+        for card in session.delayed {
+            myself.cards.insert(card, myself.settings.relearn_steps);
+        }
+        myself.save_session();
+        myself
+    }
+
+    fn with_pipeline(
+        cards: Pipeline<Id>,
+        cram: bool,
+        persist: bool,
+        cache: &mut CardCache,
+    ) -> Self {
         let mut myself = Self {
-            cards: Pipeline::new(cards),
+            cards,
             dependencies: TreeWidget::new_with_items("Dependencies".into(), vec![]),
             dependents: TreeWidget::new_with_items("Dependents".into(), vec![]),
             tab_data: TabData::default(),
@@ -36,8 +389,29 @@ impl CardReviewer<'_> {
             back: TextInput::default(),
             card_info: TextDisplay::default(),
             info: TextDisplay::default(),
+            card_shown: Instant::now(),
+            recall_time: None,
+            history: vec![],
+            relearning: BTreeSet::new(),
+            delayed: vec![],
+            introduced: BTreeSet::new(),
+            studying: false,
+            time_limit: None,
+            settings: Settings::load_or_default(),
+            session_start: Instant::now(),
+            summary: TextDisplay::default(),
+            answer: TextInput::default(),
+            answer_diff: DiffDisplay::default(),
+            suggested_grade: None,
+            focus_answer: false,
+            cram,
+            persist,
+            enter_pressed: false,
         };
-        myself.cards.next();
+        if myself.cards.current().is_none() {
+            myself.cards.next();
+        }
+        myself.save_session();
         myself.refresh(cache);
         myself
     }
@@ -49,27 +423,48 @@ impl CardReviewer<'_> {
         self.back.clear();
         self.card_info = TextDisplay::default();
         self.info = TextDisplay::default();
+        self.summary = TextDisplay::default();
+        self.answer = TextInput::default();
+        self.answer_diff = DiffDisplay::default();
+        self.suggested_grade = None;
+        self.time_limit = None;
     }
 
     // call this only when new card
     fn refresh(&mut self, cache: &mut CardCache) {
         self.clear();
         let Some(card) = self.cards.current() else {
+            if self.cards.is_done() {
+                self.summary = TextDisplay::new(self.summary_text(cache));
+            }
             return;
         };
 
         let card = cache.get_owned(*card);
 
-        self.front = TextInput::new(card.front_text().to_owned());
+        self.studying = card.reviews().is_empty() && !self.introduced.contains(&card.id());
+        self.time_limit = if self.studying {
+            None
+        } else {
+            self.settings.time_limit(card.category())
+        };
+        self.front = TextInput::new(front_display(&card));
         self.back = TextInput::new(card.back_text().to_owned());
-        self.back.hide_text = true;
+        self.back.hide_text = !self.studying;
         self.dependencies
             .replace_items(card_dependencies(card.id(), cache));
 
         self.dependents
             .replace_items(card_dependents(card.id(), cache));
-        self.card_info = TextDisplay::new(card_info(card.id(), cache));
-        self.info = TextDisplay::new(format!("{:?}", self.cards.progress()));
+        self.card_info = if self.studying {
+            TextDisplay::new(study_text(card.id(), cache))
+        } else {
+            TextDisplay::new(card_info(card.id(), cache))
+        };
+        self.card_shown = Instant::now();
+        self.recall_time = None;
+        self.focus_answer = true;
+        self.info = TextDisplay::new(self.info_text());
     }
 
     // call this whenever a change to the inputs;
@@ -80,8 +475,9 @@ impl CardReviewer<'_> {
 
         let mut card = cache.get_owned(card);
 
+        // The front of a cloze card is rendered from the note, so it can't be edited here.
         let front_text = self.front.get_text();
-        if !front_text.is_empty() {
+        if !front_text.is_empty() && active_cloze(&card).is_none() {
             card.set_front_text(front_text.as_str());
         }
         let back_text = self.back.get_text();
@@ -96,7 +492,549 @@ impl CardReviewer<'_> {
         self.dependents
             .replace_items(card_dependents(card.id(), cache));
         self.card_info = TextDisplay::new(card_info(card.id(), cache));
-        self.info = TextDisplay::new(format!("{:?}", self.cards.progress()));
+        self.info = TextDisplay::new(self.info_text());
+    }
+
+    /// Progress of the session, with re-reviews of failed cards counted separately.
+    fn info_text(&self) -> String {
+        let (progress, relearn) = progress(
+            &self.history,
+            self.cards.finished_qty(),
+            self.cards.tot_qty() + self.delayed.len(),
+            &self.settings,
+        );
+
+        let mut text = format!("{progress:?}    relearn: {relearn:?}");
+        if self.cram {
+            text.push_str("    cram");
+        }
+        if let Some(time_left) = self.time_left() {
+            text.push_str(&format!(
+                "    time left: {}s",
+                time_left.as_secs_f32().ceil()
+            ));
+        }
+        if self.last_failed().is_some() {
+            text.push_str("    'g': add the dependency the failed card was missing");
+        }
+        if let Some(recall_time) = self.recall_time {
+            text.push_str(&format!("    recall: {:.1}s", recall_time.as_secs_f32()));
+        }
+        text
+    }
+
+    /// Counts down while the back is hidden, if there's a time limit.
+    fn time_left(&self) -> Option<Duration> {
+        if !self.back.hide_text {
+            return None;
+        }
+
+        let limit = self.time_limit?;
+        Some(limit.saturating_sub(self.card_shown.elapsed()))
+    }
+
+    /// Reveals the back and suggests failing the card.
+    fn time_out(&mut self, cache: &mut CardCache) {
+        if self.settings.type_answers {
+            self.check_answer(cache);
+        } else {
+            self.reveal_back(cache);
+        }
+
+        self.suggested_grade = "1".parse::<Grade>().ok();
+        self.answer_diff.footer = "time's up, suggested grade: 1 (enter to accept)".into();
+        self.info = TextDisplay::new(format!("{}    time's up", self.info_text()));
+    }
+
+    /// Time spent on the current card so far, from showing the front until now. This is
+    /// what's stored with the review, the recall time is only a part of it.
+    fn time_spent(&self) -> Duration {
+        self.card_shown.elapsed()
+    }
+
+    /// Moves on to the next card, letting in failed cards whose delay has run out.
+    fn advance(&mut self, cache: &mut CardCache) {
+        let now = Instant::now();
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(_, due)| *due <= now);
+        self.delayed = waiting;
+
+        for (card, _) in due {
+            self.cards.insert(card, 0);
+        }
+
+        // Nothing else left to review, so no point in waiting.
+        if self.cards.queued_qty() == 0 && !self.delayed.is_empty() {
+            self.delayed.sort_by_key(|(_, due)| *due);
+            let (card, _) = self.delayed.remove(0);
+            self.cards.insert(card, 0);
+        }
+
+        self.cards.next();
+        self.save_session();
+        self.refresh(cache);
+    }
+
+    /// Saves what's left of the session, so that it can be resumed if the app is closed.
+    fn save_session(&self) {
+        if !self.persist {
+            return;
+        }
+
+        if self.cards.is_done() {
+            ReviewSession::default().save();
+            return;
+        }
+
+        let session = ReviewSession {
+            cards: self.cards.clone(),
+            relearning: self.relearning.clone(),
+            delayed: self.delayed.iter().map(|(card, _)| *card).collect(),
+            cram: self.cram,
+            introduced: self.introduced.clone(),
+        };
+        session.save();
+    }
+
+    /// Puts a failed card back in the session so that it's shown again later.
+    fn requeue(&mut self, card: Id) {
+        self.relearning.insert(card);
+
+        match self.settings.relearn_delay_secs {
+            Some(secs) => {
+                let due = Instant::now() + Duration::from_secs(secs);
+                self.delayed.push((card, due));
+            }
+            None => self.cards.insert(card, self.settings.relearn_steps),
+        }
+    }
+
+    fn failed_cards(&self) -> Vec<Id> {
+        let failed: BTreeSet<Id> = self
+            .history
+            .iter()
+            .filter(|entry| entry.is_failed())
+            .map(|entry| entry.card)
+            .collect();
+
+        failed.into_iter().collect()
+    }
+
+    /// Starts a new session with the cards that were failed in this one.
+    fn review_failed(&mut self, cache: &mut CardCache) {
+        let failed = self.failed_cards();
+        if !failed.is_empty() {
+            *self = Self::with_pipeline(Pipeline::new(failed), self.cram, self.persist, cache);
+        }
+    }
+
+    fn summary_text(&self, cache: &mut CardCache) -> String {
+        let graded: Vec<&SessionEntry> = self
+            .history
+            .iter()
+            .filter(|entry| entry.grade.is_some())
+            .collect();
+        let reviewed: BTreeSet<Id> = graded.iter().map(|entry| entry.card).collect();
+        let relearned = graded.iter().filter(|entry| entry.relearn).count();
+        let studied = self.history.iter().filter(|entry| entry.studied).count();
+        let skipped = self
+            .history
+            .iter()
+            .filter(|entry| entry.is_skipped())
+            .count();
+        let buried = self.history.iter().filter(|entry| entry.buried).count();
+        let new_cards = graded.iter().filter(|entry| entry.was_new).count();
+
+        let session_mins = self.session_start.elapsed().as_secs_f32() / 60.;
+        let avg_time = if graded.is_empty() {
+            0.
+        } else {
+            let tot: Duration = graded.iter().map(|entry| entry.time_spent).sum();
+            tot.as_secs_f32() / graded.len() as f32
+        };
+        let recall_times: Vec<Duration> = graded
+            .iter()
+            .filter_map(|entry| entry.recall_time)
+            .collect();
+        let avg_recall_time = if recall_times.is_empty() {
+            0.
+        } else {
+            let tot: Duration = recall_times.iter().sum();
+            tot.as_secs_f32() / recall_times.len() as f32
+        };
+
+        let mut text = String::from("session finished\n\n");
+        if self.cram {
+            text.push_str("crammed, so the review history of the cards wasn't changed\n\n");
+        }
+
+        text.push_str(&format!(
+            "cards reviewed: {}\nre-reviews: {relearned}\nskipped: {skipped} ({buried} buried)\nstudied: {studied}\nnew cards: {new_cards}\ntime spent: {session_mins:.1} min\navg time per card: {avg_time:.1}s\navg recall time: {avg_recall_time:.1}s\n\ngrades:\n",
+            reviewed.len()
+        ));
+
+        for key in ['1', '2', '3', '4'] {
+            let Ok(grade) = key.to_string().parse::<Grade>() else {
+                continue;
+            };
+            let qty = graded
+                .iter()
+                .filter(|entry| entry.grade.as_ref() == Some(&grade))
+                .count();
+            text.push_str(&format!("  {key} {grade:?}: {qty}\n"));
+        }
+
+        // A lapse is forgetting a card you already knew, so failing new cards doesn't count.
+        let lapses: Vec<&&SessionEntry> = graded
+            .iter()
+            .filter(|entry| entry.is_failed() && !entry.was_new && !entry.relearn)
+            .collect();
+        text.push_str(&format!("\nlapses: {}\n", lapses.len()));
+        for entry in lapses {
+            text.push_str(&format!(
+                "  {}\n",
+                front_display(&cache.get_ref(entry.card))
+            ));
+        }
+
+        let postponed: Vec<Id> = graded
+            .iter()
+            .flat_map(|entry| entry.postponed.iter().copied())
+            .collect();
+        if !postponed.is_empty() {
+            text.push_str(&format!(
+                "\npostponed, as a dependency was failed: {}\n",
+                postponed.len()
+            ));
+            for card in postponed {
+                text.push_str(&format!("  {}\n", front_display(&cache.get_ref(card))));
+            }
+        }
+
+        let leeches: Vec<&&SessionEntry> = graded.iter().filter(|entry| entry.leech).collect();
+        if !leeches.is_empty() {
+            let suspended = if self.settings.suspend_leeches {
+                ", suspended"
+            } else {
+                ""
+            };
+            text.push_str(&format!("\nnew leeches{suspended}: {}\n", leeches.len()));
+            for entry in leeches {
+                text.push_str(&format!(
+                    "  {}\n",
+                    front_display(&cache.get_ref(entry.card))
+                ));
+            }
+        }
+
+        let mut forecast = [0; 4];
+        for card in &reviewed {
+            let card = cache.get_ref(*card);
+            let target = self.settings.thresholds(card.category()).max_recall_rate;
+            let Some(days) = days_until_due(card.stability(), target) else {
+                continue;
+            };
+
+            let idx = match days {
+                days if days < 1. => 0,
+                days if days < 7. => 1,
+                days if days < 30. => 2,
+                _ => 3,
+            };
+            forecast[idx] += 1;
+        }
+
+        text.push_str(&format!(
+            "\nnext due:\n  within a day: {}\n  within a week: {}\n  within a month: {}\n  later: {}\n",
+            forecast[0], forecast[1], forecast[2], forecast[3]
+        ));
+
+        text.push_str("\nr: review the failed cards again    enter: return to menu");
+        text
+    }
+
+    fn reveal_back(&mut self, cache: &mut CardCache) {
+        self.recall_time = Some(self.card_shown.elapsed());
+        self.info = TextDisplay::new(self.info_text());
+        self.back.hide_text = false;
+        self.evaluate_current(cache, CardAction::PlayBackAudio);
+    }
+
+    /// Compares the typed answer with the answer of the card and suggests a grade.
+    fn check_answer(&mut self, cache: &mut CardCache) {
+        let Some(card) = self.cards.current().copied() else {
+            return;
+        };
+
+        // The answer to a cloze card is what's blanked out on the front, not the back.
+        let card = cache.get_ref(card);
+        let correct = match active_cloze(&card) {
+            Some(number) => cloze_answer(card.front_text(), number),
+            None => card.back_text().trim().to_owned(),
+        };
+        let diff = char_diff(self.answer.get_text().trim(), &correct);
+        let similarity = similarity(&diff);
+        let grade_key = suggested_grade_key(similarity);
+
+        self.answer_diff = DiffDisplay {
+            diff,
+            footer: format!(
+                "similarity: {:.0}%    suggested grade: {grade_key} (enter to accept)",
+                similarity * 100.
+            ),
+        };
+        self.suggested_grade = grade_key.to_string().parse::<Grade>().ok();
+        self.tab_data.is_selected = false;
+        self.reveal_back(cache);
+    }
+
+    fn grade_card(&mut self, card: Id, grade: Grade, cache: &mut CardCache) {
+        let time_spent = self.time_spent();
+        let was_new = cache.get_ref(card).reviews().is_empty();
+        let failed = !grade.is_succesful_recall();
+        let relearn = self.relearning.contains(&card);
+        let mut entry = SessionEntry {
+            card,
+            grade: Some(grade.clone()),
+            relearn,
+            was_new,
+            leech: false,
+            postponed: vec![],
+            studied: false,
+            buried: false,
+            dropped: false,
+            time_spent,
+            recall_time: self.recall_time,
+        };
+
+        if self.cram {
+            PracticeLog::record(card, grade, time_spent);
+        } else {
+            cache.get_owned(card).new_review(grade, time_spent);
+            if let Some(recall_time) = self.recall_time {
+                RecallLog::record(card, recall_time, time_spent.saturating_sub(recall_time));
+            }
+            entry.leech = failed && mark_leech(card, cache, &self.settings);
+            if failed {
+                entry.postponed = self.postpone_dependents(card, cache);
+            }
+
+            // Relearning a failed card doesn't use up more of the daily limit.
+            if !relearn {
+                DailyCount::record(cache.get_ref(card).category(), was_new);
+            }
+        }
+
+        if entry.is_requeued(&self.settings) {
+            self.requeue(card);
+        } else {
+            self.relearning.remove(&card);
+        }
+
+        self.history.push(entry);
+        self.advance(cache);
+    }
+
+    /// Pushes the card to the end of the session, or buries it until tomorrow. Unfinished
+    /// cards can't be graded, so they'd keep coming back, they're dropped from the session
+    /// instead.
+    fn skip(&mut self, card: Id, bury: bool, cache: &mut CardCache) {
+        SkipLog::record(card, bury);
+        let dropped = !bury && !cache.get_ref(card).is_finished();
+
+        if bury {
+            let tomorrow = Duration::from_secs((current_time().as_secs() / 86400 + 1) * 86400);
+            cache
+                .get_owned(card)
+                .set_suspended(IsSuspended::TrueUntil(tomorrow));
+        } else if !dropped {
+            self.cards.insert(card, self.cards.queued_qty());
+        }
+
+        self.history.push(SessionEntry {
+            card,
+            grade: None,
+            relearn: self.relearning.contains(&card),
+            was_new: false,
+            leech: false,
+            postponed: vec![],
+            studied: false,
+            buried: bury,
+            dropped,
+            time_spent: self.time_spent(),
+            recall_time: self.recall_time,
+        });
+        self.advance(cache);
+    }
+
+    /// The previous card, if it was failed.
+    fn last_failed(&self) -> Option<Id> {
+        self.history
+            .last()
+            .filter(|entry| entry.is_failed())
+            .map(|entry| entry.card)
+    }
+
+    /// Failing a card is often down to a gap in what it builds on, this fills it in.
+    fn capture_gap(&mut self, cache: &mut CardCache) {
+        let Some(card) = self.last_failed() else {
+            return;
+        };
+
+        let category = cache.get_ref(card).category().to_owned();
+        let add_card = AddCard::new(
+            "Add the missing dependency",
+            category,
+            Some(DependencyStatus::Dependent(card)),
+        )
+        .with_tag(GAP_TAG);
+
+        self.set_popup(Box::new(add_card));
+    }
+
+    /// Moves on from a new card that has been studied, it's tested later in the session.
+    fn finish_study(&mut self, card: Id, cache: &mut CardCache) {
+        self.introduced.insert(card);
+        self.history.push(SessionEntry {
+            card,
+            grade: None,
+            relearn: false,
+            was_new: true,
+            leech: false,
+            postponed: vec![],
+            studied: true,
+            buried: false,
+            dropped: false,
+            time_spent: self.time_spent(),
+            recall_time: self.recall_time,
+        });
+        self.cards.insert(card, self.settings.relearn_steps);
+        self.advance(cache);
+    }
+
+    /// Takes the cards that build on a failed card out of the session, as there's no point
+    /// in reviewing them before the failed card is known again.
+    fn postpone_dependents(&mut self, card: Id, cache: &mut CardCache) -> Vec<Id> {
+        let mut postponed = vec![];
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![card];
+
+        while let Some(card) = stack.pop() {
+            for dependent in cache.dependents(card) {
+                if !visited.insert(dependent) {
+                    continue;
+                }
+
+                if self.cards.is_queued(&dependent) {
+                    while self.cards.is_queued(&dependent) {
+                        self.cards.remove(&dependent);
+                    }
+                    postponed.push(dependent);
+                }
+
+                stack.push(dependent);
+            }
+        }
+
+        postponed
+    }
+
+    /// Goes back to the previous card, removing the review it got in this session.
+    fn undo(&mut self, cache: &mut CardCache) {
+        let Some(entry) = self.history.pop() else {
+            return;
+        };
+
+        if entry.grade.is_some() && self.cram {
+            PracticeLog::pop();
+        } else if entry.grade.is_some() {
+            cache.pop_review(entry.card);
+            if entry.recall_time.is_some() {
+                RecallLog::pop();
+            }
+
+            if !entry.relearn {
+                DailyCount::unrecord(cache.get_ref(entry.card).category(), entry.was_new);
+            }
+
+            if entry.leech {
+                unmark_leech(entry.card, cache);
+            }
+        }
+
+        self.cards.prev();
+
+        // The requeued copy of a failed card might already be the current one, so this has to
+        // happen after stepping back.
+        if entry.is_requeued(&self.settings) {
+            match self
+                .delayed
+                .iter()
+                .position(|(card, _)| *card == entry.card)
+            {
+                Some(idx) => {
+                    self.delayed.remove(idx);
+                }
+                None => self.cards.remove(&entry.card),
+            }
+        }
+
+        if entry.relearn {
+            self.relearning.insert(entry.card);
+        } else {
+            self.relearning.remove(&entry.card);
+        }
+
+        if entry.studied {
+            self.cards.remove(&entry.card);
+            self.introduced.remove(&entry.card);
+        }
+
+        if entry.is_skipped() {
+            SkipLog::pop();
+
+            if entry.buried {
+                cache
+                    .get_owned(entry.card)
+                    .set_suspended(IsSuspended::False);
+            } else if !entry.dropped {
+                self.cards.remove(&entry.card);
+            }
+        }
+
+        for card in entry.postponed {
+            self.cards.insert(card, self.cards.queued_qty());
+        }
+
+        self.save_session();
+        self.refresh(cache);
+    }
+
+    /// Returns whether the enter key was used for something.
+    fn handle_enter(&mut self, cache: &mut CardCache) -> bool {
+        if self.cards.is_done() {
+            self.exit_tab();
+            return true;
+        }
+
+        let Some(card) = self.cards.current().copied() else {
+            return false;
+        };
+
+        if self.studying {
+            self.finish_study(card, cache);
+            return true;
+        }
+
+        if !self.back.hide_text && cache.get_ref(card).is_finished() {
+            if let Some(grade) = self.suggested_grade.clone() {
+                self.grade_card(card, grade, cache);
+                return true;
+            }
+        }
+
+        false
     }
 
     fn play_front_audio(&mut self, cache: &mut CardCache) {
@@ -104,6 +1042,54 @@ impl CardReviewer<'_> {
     }
 }
 
+/// The closer the typed answer is to the correct one, the better the grade.
+fn suggested_grade_key(similarity: f32) -> char {
+    match similarity {
+        x if x >= 0.95 => '4',
+        x if x >= 0.8 => '3',
+        x if x >= 0.5 => '2',
+        _ => '1',
+    }
+}
+
+/// Days from the last review until the recall rate drops to the target.
+pub fn days_until_due(stability: Option<Duration>, target: f32) -> Option<f32> {
+    let stability = stability?.as_secs_f32() / 86400.;
+    Some(stability * target.ln() / 0.9_f32.ln())
+}
+
+/// When the card is due, as time since the unix epoch. Cards that were never reviewed aren't due.
+pub fn due_time(card: &SavedCard, settings: &Settings) -> Option<Duration> {
+    let last_review = card.reviews().last()?.timestamp;
+    let target = settings.thresholds(card.category()).max_recall_rate;
+    let days = days_until_due(card.stability(), target)?;
+    Some(last_review + Duration::from_secs_f32(days.max(0.) * 86400.))
+}
+
+/// Shown instead of the card info when a new card is studied, with the answers of the
+/// dependencies so it can be learned together with what it builds on.
+fn study_text(card: Id, cache: &mut CardCache) -> String {
+    let mut text = String::from(
+        "new card, press enter when you've studied it. It's tested later in the session.\n",
+    );
+
+    let dependencies = cache.dependencies(card);
+    if !dependencies.is_empty() {
+        text.push_str("\ndependencies:\n");
+    }
+
+    for dependency in dependencies {
+        let dependency = cache.get_ref(dependency);
+        text.push_str(&format!(
+            "  {}\n  -> {}\n\n",
+            front_display(&dependency),
+            dependency.back_text()
+        ));
+    }
+
+    text
+}
+
 pub fn card_info(card: Id, cache: &mut CardCache) -> String {
     let card = cache.get_ref(card);
     let suspended = card.is_suspended();
@@ -123,8 +1109,13 @@ pub fn card_info(card: Id, cache: &mut CardCache) -> String {
     .as_secs_f32()
         / 86400.;
     let importance = card.weighted_importance(&mut cache.inner.lock().unwrap());
+    let last_review_time = card
+        .reviews()
+        .last()
+        .map(|r| r.time_spent.as_secs_f32())
+        .unwrap_or_default();
 
-    format!("suspended: {}\nfinished: {}\nresolved: {}\nstability: {:?}\nreviews: {}\nrecall rate: {:?}\nlapses: {}\nlast review: {:.2} days\npriority : {priority}\nweighted importance: {importance}", suspended, finished, resolved, stability, reviews, recall_rate, lapses, last_review)
+    format!("suspended: {}\nfinished: {}\nresolved: {}\nstability: {:?}\nreviews: {}\nrecall rate: {:?}\nlapses: {}\nlast review: {:.2} days\nlast review time: {:.1}s\npriority : {priority}\nweighted importance: {importance}", suspended, finished, resolved, stability, reviews, recall_rate, lapses, last_review, last_review_time)
 }
 
 impl Tab for CardReviewer<'_> {
@@ -136,22 +1127,79 @@ impl Tab for CardReviewer<'_> {
     }
 
     fn widgets(&mut self, area: Rect) -> Vec<(&mut dyn Widget<AppData = Self::AppState>, Rect)> {
+        if self.cards.is_done() {
+            return vec![(&mut self.summary, area)];
+        }
+
         let (info_bar, area) = split_off(area, 1, Retning::Up);
 
         let (card_area, info_area) = hsplit2(area, 50, 50);
         let (dependency_area, card_info_area) = vsplit2(info_area, 50, 50);
         let (dependency_area, dependents_area) = vsplit2(dependency_area, 50, 50);
-        let (card_area, _) = vsplit2(card_area, 50, 50);
+        let (card_area, answer_area) = vsplit2(card_area, 50, 50);
         let (front, back) = vsplit2(card_area, 50, 50);
 
-        vec![
+        let mut widgets: Vec<(&mut dyn Widget<AppData = Self::AppState>, Rect)> = vec![
             (&mut self.front, front),
             (&mut self.back, back),
             (&mut self.card_info, card_info_area),
             (&mut self.dependencies, dependency_area),
             (&mut self.dependents, dependents_area),
             (&mut self.info, info_bar),
-        ]
+        ];
+
+        if self.settings.type_answers {
+            if self.back.hide_text {
+                widgets.push((&mut self.answer, answer_area));
+            } else {
+                widgets.push((&mut self.answer_diff, answer_area));
+            }
+        }
+
+        widgets
+    }
+
+    fn pre_render_hook(&mut self, cache: &mut Self::AppState) {
+        if let Some(time_left) = self.time_left() {
+            if time_left.is_zero() {
+                self.time_out(cache);
+            } else {
+                self.info = TextDisplay::new(self.info_text());
+            }
+        }
+
+        if std::mem::take(&mut self.focus_answer)
+            && self.settings.type_answers
+            && self.back.hide_text
+            && !self.cards.is_done()
+        {
+            let id = self.answer.id();
+            self.move_to_id(&id);
+            self.tab_data.is_selected = true;
+        }
+    }
+
+    fn pre_keyhandler_hook(&mut self, key: crossterm::event::KeyEvent) {
+        self.enter_pressed = key.code == KeyCode::Enter && !self.tab_data.is_selected;
+    }
+
+    fn after_keyhandler(&mut self, cache: &mut Self::AppState) {
+        if std::mem::take(&mut self.enter_pressed) && self.handle_enter(cache) {
+            self.tab_data.is_selected = false;
+        }
+    }
+
+    fn tab_keyhandler_selected(
+        &mut self,
+        cache: &mut Self::AppState,
+        key: crossterm::event::KeyEvent,
+    ) -> bool {
+        if self.is_selected(&self.answer) && key.code == KeyCode::Enter {
+            self.check_answer(cache);
+            return false;
+        }
+
+        true
     }
 
     fn tabdata(&mut self) -> &mut TabData<Self::AppState, Self::ReturnType> {
@@ -163,9 +1211,25 @@ impl Tab for CardReviewer<'_> {
         cache: &mut Self::AppState,
         key: crossterm::event::KeyEvent,
     ) -> bool {
+        if key.code == KeyCode::Char('u') {
+            self.undo(cache);
+            return false;
+        }
+
+        if key.code == KeyCode::Char('g') {
+            self.capture_gap(cache);
+            return false;
+        }
+
+        if self.cards.is_done() {
+            if key.code == KeyCode::Char('r') {
+                self.review_failed(cache);
+            }
+            return false;
+        }
+
         let Some(card) = self.cards.current else {
-            self.cards.next();
-            self.refresh(cache);
+            self.advance(cache);
             return true;
         };
 
@@ -173,6 +1237,11 @@ impl Tab for CardReviewer<'_> {
 
         let key = key.code;
 
+        if self.studying && key == KeyCode::Char(' ') {
+            self.finish_study(card, cache);
+            return false;
+        }
+
         if let KeyCode::Char(c) = key {
             if let Ok(action) = CardAction::from_char(c.to_string().as_str()) {
                 self.evaluate_current(cache, action);
@@ -180,21 +1249,16 @@ impl Tab for CardReviewer<'_> {
                 match c {
                     ' ' => {
                         if self.back.hide_text {
-                            self.back.hide_text = false;
-                            self.evaluate_current(cache, CardAction::PlayBackAudio);
+                            self.reveal_back(cache);
                             return false;
                         }
                     }
-                    'n' => {
-                        self.cards.next();
-                        self.refresh(cache);
-                    }
+                    'n' => self.skip(card, false, cache),
+                    'N' => self.skip(card, true, cache),
                     _ => {
                         if let Ok(grade) = c.to_string().parse::<Grade>() {
-                            if is_finished && !self.back.hide_text {
-                                cache.get_owned(card).new_review(grade, Duration::default());
-                                self.cards.next();
-                                self.refresh(cache);
+                            if is_finished && !self.back.hide_text && !self.studying {
+                                self.grade_card(card, grade, cache);
                                 return false;
                             }
                         }
@@ -220,3 +1284,77 @@ impl CurrentCard for CardReviewer<'_> {
 }
 
 impl CardActionTrait for CardReviewer<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use speki_backend::{card::Card, categories::Category};
+
+    use super::*;
+
+    /// Keeps the cards and logs that the tests write apart from the real ones.
+    fn temp_home() {
+        let home = std::env::temp_dir().join(format!("remynder-test-{}", std::process::id()));
+        std::fs::create_dir_all(&home).unwrap();
+        std::env::set_var("HOME", home);
+    }
+
+    #[test]
+    fn cram_keeps_review_history() {
+        temp_home();
+        let mut cache = CardCache::new();
+        let card = Card::new_simple("front".into(), "back".into())
+            .save_new_card(&Category::root(), &mut cache.inner.lock().unwrap())
+            .id();
+
+        let mut reviewer = CardReviewer::cram(vec![card], &mut cache);
+        reviewer.reveal_back(&mut cache);
+        reviewer.grade_card(card, "3".parse().unwrap(), &mut cache);
+
+        assert!(cache.get_ref(card).reviews().is_empty());
+    }
+
+    fn entry(grade: Option<&str>, relearn: bool) -> SessionEntry {
+        SessionEntry {
+            card: Id::default(),
+            grade: grade.map(|grade| grade.parse().unwrap()),
+            relearn,
+            was_new: false,
+            leech: false,
+            postponed: vec![],
+            studied: false,
+            buried: false,
+            dropped: false,
+            time_spent: Duration::default(),
+            recall_time: None,
+        }
+    }
+
+    #[test]
+    fn skipped_relearn_counted_once() {
+        // Two cards, the first one is failed and its re-review is skipped twice. Every entry
+        // finishes a card in the queue, the fail and the skips each add one to it.
+        let settings = Settings::default();
+        let mut history = vec![entry(Some("1"), false)];
+        assert_eq!(progress(&history, 1, 3, &settings), ((1, 2), (0, 1)));
+
+        history.push(entry(None, true));
+        history.push(entry(None, true));
+        assert_eq!(progress(&history, 3, 5, &settings), ((1, 2), (0, 1)));
+
+        history.push(entry(Some("3"), true));
+        assert_eq!(progress(&history, 4, 5, &settings), ((1, 2), (1, 1)));
+    }
+
+    #[test]
+    fn suspended_leech_not_requeued() {
+        let settings = Settings {
+            suspend_leeches: true,
+            ..Default::default()
+        };
+        let mut leech = entry(Some("1"), false);
+        leech.leech = true;
+
+        // Two cards, the first one becomes a leech and is suspended instead of requeued.
+        assert_eq!(progress(&[leech], 1, 2, &settings), ((1, 2), (0, 0)));
+    }
+}
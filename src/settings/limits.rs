@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use speki_backend::{categories::Category, common::current_time};
+use vedvaring::SingletonPersist;
+
+use super::Settings;
+
+/// Caps on how many cards are reviewed in a day.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Limits {
+    /// Cards with no reviews.
+    pub new_cards: Option<usize>,
+    /// All reviews, the new cards included.
+    pub reviews: Option<usize>,
+}
+
+fn today() -> u64 {
+    current_time().as_secs() / 86400
+}
+
+/// Whether the category path is the given one or one of its subcategories.
+fn is_within(category: &str, parent: &str) -> bool {
+    parent.is_empty()
+        || category == parent
+        || category
+            .strip_prefix(parent)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// The cards reviewed today, across all the sessions. Keyed by the category path.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DailyCount {
+    day: u64,
+    new_cards: BTreeMap<String, usize>,
+    reviews: BTreeMap<String, usize>,
+}
+
+impl DailyCount {
+    /// Starts over when the day has changed since the count was saved.
+    pub fn today() -> Self {
+        let count = Self::load();
+        if count.day == today() {
+            count
+        } else {
+            Self {
+                day: today(),
+                ..Default::default()
+            }
+        }
+    }
+
+    pub fn record(category: &Category, was_new: bool) {
+        let mut count = Self::today();
+        count.add(category, was_new);
+        count.save();
+    }
+
+    /// For when a review gets undone.
+    pub fn unrecord(category: &Category, was_new: bool) {
+        let mut count = Self::today();
+        let key = category.joined();
+
+        if was_new {
+            if let Some(qty) = count.new_cards.get_mut(&key) {
+                *qty = qty.saturating_sub(1);
+            }
+        }
+
+        if let Some(qty) = count.reviews.get_mut(&key) {
+            *qty = qty.saturating_sub(1);
+        }
+
+        count.save();
+    }
+
+    fn add(&mut self, category: &Category, was_new: bool) {
+        let key = category.joined();
+
+        if was_new {
+            *self.new_cards.entry(key.clone()).or_default() += 1;
+        }
+
+        *self.reviews.entry(key).or_default() += 1;
+    }
+
+    fn new_cards_in(&self, category: &str) -> usize {
+        sum_within(&self.new_cards, category)
+    }
+
+    fn reviews_in(&self, category: &str) -> usize {
+        sum_within(&self.reviews, category)
+    }
+}
+
+fn sum_within(counts: &BTreeMap<String, usize>, parent: &str) -> usize {
+    counts
+        .iter()
+        .filter(|(category, _)| is_within(category, parent))
+        .map(|(_, qty)| qty)
+        .sum()
+}
+
+impl SingletonPersist for DailyCount {
+    fn name() -> String {
+        "daily_count".into()
+    }
+
+    fn dir_name() -> String {
+        "speki".into()
+    }
+}
+
+/// What's left of today's limits, used up as cards are picked for a review.
+pub struct Allowance<'a> {
+    settings: &'a Settings,
+    count: DailyCount,
+}
+
+impl<'a> Allowance<'a> {
+    pub fn new(settings: &'a Settings) -> Self {
+        Self {
+            settings,
+            count: DailyCount::today(),
+        }
+    }
+
+    /// Whether the card fits in the allowance, and if so it's counted.
+    pub fn take(&mut self, category: &Category, is_new: bool) -> bool {
+        let mut limits = vec![(String::new(), &self.settings.limits)];
+        limits.extend(self.settings.category_limits(category));
+
+        for (category, limits) in limits {
+            if is_new && remaining(limits.new_cards, self.count.new_cards_in(&category)) == Some(0)
+            {
+                return false;
+            }
+
+            if remaining(limits.reviews, self.count.reviews_in(&category)) == Some(0) {
+                return false;
+            }
+        }
+
+        self.count.add(category, is_new);
+        true
+    }
+
+    /// How much is left of the global limits and the category overrides.
+    pub fn text(&self) -> String {
+        let mut text = format!(
+            "left today: {}\n",
+            self.remaining_text(&self.settings.limits, "")
+        );
+
+        for (category, limits) in &self.settings.category_limits {
+            text.push_str(&format!(
+                "  {category}: {}\n",
+                self.remaining_text(limits, category)
+            ));
+        }
+
+        text
+    }
+
+    fn remaining_text(&self, limits: &Limits, category: &str) -> String {
+        let show = |qty: Option<usize>| match qty {
+            Some(qty) => qty.to_string(),
+            None => "no limit".to_string(),
+        };
+
+        let new_cards = remaining(limits.new_cards, self.count.new_cards_in(category));
+        let reviews = remaining(limits.reviews, self.count.reviews_in(category));
+
+        format!("{} new cards, {} reviews", show(new_cards), show(reviews))
+    }
+}
+
+fn remaining(limit: Option<usize>, used: usize) -> Option<usize> {
+    limit.map(|limit| limit.saturating_sub(used))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn within() {
+        assert!(is_within("lang/rust", ""));
+        assert!(is_within("lang/rust", "lang"));
+        assert!(is_within("lang", "lang"));
+        assert!(!is_within("language", "lang"));
+        assert!(!is_within("lang", "lang/rust"));
+    }
+}
@@ -0,0 +1,185 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use speki_backend::categories::Category;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use vedvaring::SingletonPersist;
+
+mod limits;
+pub use limits::*;
+
+/// User settings, stored next to the card collection.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+    /// How many other cards to review before a failed card is shown again.
+    pub relearn_steps: usize,
+    /// If set, failed cards are shown again after this many seconds instead of after
+    /// `relearn_steps` cards.
+    pub relearn_delay_secs: Option<u64>,
+    /// Type in the answer instead of just revealing it.
+    pub type_answers: bool,
+    /// Cards that lapse this many times in a row are tagged as leeches.
+    pub leech_lapses: u32,
+    /// Suspend the cards when they become leeches.
+    pub suspend_leeches: bool,
+    /// Time to recall a card before the back is revealed and it's suggested to be failed.
+    pub time_limit_secs: Option<u64>,
+    /// Overrides of the time limit, keyed by the category path.
+    pub category_time_limits: BTreeMap<String, u64>,
+    pub thresholds: Thresholds,
+    /// Overrides of the thresholds, keyed by the category path, e.g. "lang/rust".
+    /// They also apply to the subcategories.
+    pub category_thresholds: BTreeMap<String, Thresholds>,
+    pub limits: Limits,
+    /// Overrides of the daily limits, they cap the category and its subcategories on top
+    /// of the global limits.
+    pub category_limits: BTreeMap<String, Limits>,
+    /// The order of the cards, keyed by the name of the review mode.
+    pub queue_orders: BTreeMap<String, QueueOrder>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            relearn_steps: 5,
+            relearn_delay_secs: None,
+            type_answers: false,
+            leech_lapses: 4,
+            suspend_leeches: false,
+            time_limit_secs: None,
+            category_time_limits: BTreeMap::new(),
+            thresholds: Thresholds::default(),
+            category_thresholds: BTreeMap::new(),
+            limits: Limits::default(),
+            category_limits: BTreeMap::new(),
+            queue_orders: BTreeMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// The file is meant to be edited by hand, so unlike `load` this doesn't panic on a
+    /// typo, it returns what's wrong with the file.
+    pub fn try_load() -> Result<Self, String> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(Self::load());
+        }
+
+        let text = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// The defaults are used while the settings file can't be read, the review menu shows
+    /// why.
+    pub fn load_or_default() -> Self {
+        Self::try_load().unwrap_or_default()
+    }
+
+    /// The thresholds of the closest category that overrides them, or the global ones.
+    pub fn thresholds(&self, category: &Category) -> &Thresholds {
+        closest_override(&self.category_thresholds, category)
+            .map(|(_, thresholds)| thresholds)
+            .unwrap_or(&self.thresholds)
+    }
+
+    /// The limits of the closest category that overrides them, with its path.
+    pub fn category_limits(&self, category: &Category) -> Option<(String, &Limits)> {
+        closest_override(&self.category_limits, category)
+    }
+
+    pub fn time_limit(&self, category: &Category) -> Option<Duration> {
+        closest_override(&self.category_time_limits, category)
+            .map(|(_, secs)| *secs)
+            .or(self.time_limit_secs)
+            .map(Duration::from_secs)
+    }
+
+    pub fn queue_order(&self, mode: &str) -> QueueOrder {
+        self.queue_orders.get(mode).copied().unwrap_or_default()
+    }
+
+    /// Switches the review mode to the next queue order.
+    pub fn cycle_queue_order(&mut self, mode: &str) {
+        let current = self.queue_order(mode);
+        let next = QueueOrder::iter()
+            .cycle()
+            .skip_while(|order| *order != current)
+            .nth(1)
+            .unwrap_or_default();
+
+        self.queue_orders.insert(mode.to_owned(), next);
+    }
+}
+
+fn closest_override<'a, T>(
+    overrides: &'a BTreeMap<String, T>,
+    category: &Category,
+) -> Option<(String, &'a T)> {
+    let mut category = category.clone();
+
+    loop {
+        let path = category.joined();
+        if let Some(value) = overrides.get(&path) {
+            return Some((path, value));
+        }
+
+        category.0.pop()?;
+    }
+}
+
+impl SingletonPersist for Settings {
+    fn name() -> String {
+        "settings".into()
+    }
+
+    fn dir_name() -> String {
+        "speki".into()
+    }
+}
+
+/// The order that the cards of a review are shown in.
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, EnumIter, strum_macros::Display,
+)]
+pub enum QueueOrder {
+    #[default]
+    Random,
+    LowestRecallRate,
+    HighestImportance,
+    /// Dependencies before their dependents.
+    Dependencies,
+    /// Takes turns between the categories.
+    InterleavedCategories,
+    /// The cards that have been due for the longest time first.
+    OldestDue,
+}
+
+/// Decides which cards are due in a regular review.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Thresholds {
+    /// A card is due when its recall rate drops below this.
+    pub max_recall_rate: f32,
+    /// All dependencies of a card must be at least this likely to be recalled...
+    pub dependency_min_recall_rate: f32,
+    /// ...and have at least this stability.
+    pub dependency_min_stability_days: f32,
+    pub min_importance: f32,
+    /// Cards reviewed more recently than this aren't due.
+    pub min_days_since_review: f32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            max_recall_rate: 0.9,
+            dependency_min_recall_rate: 0.95,
+            dependency_min_stability_days: 1.0,
+            min_importance: 1.0,
+            min_days_since_review: 1.0,
+        }
+    }
+}
@@ -1,14 +1,17 @@
+use std::cmp::Ordering;
+
 use crossterm::event::KeyCode;
 use mischef::{Tab, TabData, Widget};
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+use serde::{Deserialize, Serialize};
 use speki_backend::card::ReviewType;
 use speki_backend::{filter::FilterUtil, Id};
 use strum_macros::{EnumIter, EnumString};
 
-use crate::popups::{ActionPicker, CardInspector};
+use crate::popups::{ActionPicker, CardInspector, SavedSearch, SearchChoice};
 use crate::utils::{
-    card_dependencies, card_dependents, StatefulList, StatefulTree, TextDisplay, TreeWidget,
+    card_dependencies, card_dependents, created_time, creation_times_known, front_display,
+    parse_query, unmark_leech, DateField, Query, StatefulList, StatefulTree, TextDisplay,
+    TextInput, TreeWidget, LEECH_TAG, QUERY_HELP,
 };
 
 use crate::widgets::card_info;
@@ -18,16 +21,67 @@ use crate::{hsplit2, split_off, vsplit2, CardAction, CardActionTrait, CardCache,
 
 use super::review::CurrentCard;
 
-#[derive(EnumString, EnumIter, strum_macros::Display)]
+#[derive(EnumString, EnumIter, strum_macros::Display, Clone, Copy, Serialize, Deserialize)]
 pub enum Sorter {
     LastModified,
+    Created,
+    LastReview,
     RecallRate,
+    Stability,
+    Strength,
+    Lapses,
+    Reviews,
+    Priority,
+    Importance,
+    Dependents,
+    Category,
     AlphaBetical,
     Shuffled,
 }
 
+enum SortValue {
+    Num(f64),
+    Text(String),
+}
+
+impl SortValue {
+    fn compare(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Num(a), Self::Num(b)) => a.total_cmp(b),
+            (Self::Text(a), Self::Text(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+fn sort_value(sorter: Sorter, id: Id, cache: &mut CardCache) -> SortValue {
+    let card = cache.get_ref(id);
+    let secs = |duration: Option<std::time::Duration>| {
+        SortValue::Num(duration.unwrap_or_default().as_secs_f64())
+    };
+
+    match sorter {
+        Sorter::LastModified => secs(Some(card.last_modified())),
+        Sorter::Created => secs(created_time(&card)),
+        Sorter::LastReview => secs(card.reviews().last().map(|review| review.timestamp)),
+        Sorter::RecallRate => SortValue::Num(card.recall_rate().unwrap_or_default() as f64),
+        Sorter::Stability => secs(card.stability()),
+        Sorter::Strength => secs(card.strength()),
+        Sorter::Lapses => SortValue::Num(card.lapses() as f64),
+        Sorter::Reviews => SortValue::Num(card.reviews().len() as f64),
+        Sorter::Priority => SortValue::Num(card.priority().as_float() as f64),
+        Sorter::Importance => {
+            SortValue::Num(card.weighted_importance(&mut cache.inner.lock().unwrap()) as f64)
+        }
+        Sorter::Dependents => SortValue::Num(cache.dependents(id).len() as f64),
+        Sorter::Category => SortValue::Text(card.category().joined()),
+        Sorter::AlphaBetical => SortValue::Text(card.front_text().to_string()),
+        Sorter::Shuffled => SortValue::Num(rand::random()),
+    }
+}
+
 pub struct Browser<'a> {
-    filter: FilterUtil,
+    filter: Query,
     card_list: StatefulList<Id>,
     front_card: TextDisplay,
     back_card: TextDisplay,
@@ -35,11 +89,22 @@ pub struct Browser<'a> {
     dependencies: TreeWidget<'a, Id>,
     dependents: TreeWidget<'a, Id>,
     filter_input: InputTable<'a, FilterUtil>,
+    /// A search like `tag:rust recall<0.8`, the quicker way to fill in `filter_input`.
+    query: TextInput<'a>,
+    /// How many cards the query matched, or why it couldn't be parsed.
+    query_status: String,
     tab_data: TabData<CardCache, ReturnType>,
     sort_choice: EnumChoice<Sorter>,
-    sort_dir: bool,
+    sort_info: TextDisplay,
+    sort_by: Option<Sorter>,
+    /// Breaks the ties of `sort_by`.
+    then_by: Option<Sorter>,
+    descending: bool,
     is_popup: bool,
     cache_len: usize,
+    /// Only show the leeches, so they can be rewritten, split up or get the dependencies
+    /// they're missing.
+    leech_list: bool,
 }
 
 impl CurrentCard for Browser<'_> {
@@ -47,8 +112,13 @@ impl CurrentCard for Browser<'_> {
         self.card_list.selected().copied()
     }
 
+    /// The marked cards, or the current one if none are marked.
     fn selected_cards(&self) -> Vec<Id> {
-        self.card_list.items.clone()
+        if self.card_list.marked.is_empty() {
+            self.selected_card().into_iter().collect()
+        } else {
+            self.card_list.marked_items()
+        }
     }
 }
 
@@ -56,10 +126,10 @@ impl CardActionTrait for Browser<'_> {}
 
 impl Browser<'_> {
     pub fn new(cache: &mut CardCache, is_popup: bool) -> Self {
-        let filter = FilterUtil::default();
+        let filter = Query::default();
         let list = StatefulList::with_items(cache.all_ids());
         let mune = EnumChoice::<Sorter>::new();
-        Self {
+        let mut browser = Self {
             filter,
             card_list: list,
             front_card: TextDisplay::default(),
@@ -68,11 +138,97 @@ impl Browser<'_> {
             dependents: TreeWidget::new_with_items("Dependents".into(), vec![]),
             tab_data: TabData::default(),
             filter_input: InputTable::new(),
+            query: TextInput::default(),
+            query_status: String::new(),
             sort_choice: mune,
-            sort_dir: false,
+            sort_info: TextDisplay::default(),
+            sort_by: None,
+            then_by: None,
+            descending: false,
             is_popup,
             info: TextDisplay::default(),
             cache_len: cache.card_qty(),
+            leech_list: false,
+        };
+        browser.update_sort_info();
+        browser
+    }
+
+    fn update_sort_info(&mut self) {
+        let dir = if self.descending { "desc" } else { "asc" };
+        let sorter = |sorter: Option<Sorter>| match sorter {
+            Some(sorter) => sorter.to_string(),
+            None => "-".to_string(),
+        };
+
+        self.sort_info = TextDisplay::new(format!(
+            "{} then {} ({dir})    enter: sort  b: then by  d: direction",
+            sorter(self.sort_by),
+            sorter(self.then_by)
+        ));
+    }
+
+    fn sort_cards(&mut self, cache: &mut CardCache) {
+        let Some(sort_by) = self.sort_by else {
+            return;
+        };
+
+        let then_by = self.then_by;
+        let mut keyed: Vec<(SortValue, Option<SortValue>, Id)> = self
+            .card_list
+            .items
+            .iter()
+            .map(|id| {
+                let then = then_by.map(|then_by| sort_value(then_by, *id, cache));
+                (sort_value(sort_by, *id, cache), then, *id)
+            })
+            .collect();
+
+        keyed.sort_by(|a, b| {
+            a.0.compare(&b.0).then_with(|| match (&a.1, &b.1) {
+                (Some(a), Some(b)) => a.compare(b),
+                _ => Ordering::Equal,
+            })
+        });
+
+        if self.descending {
+            keyed.reverse();
+        }
+
+        self.card_list.items = keyed.into_iter().map(|(_, _, id)| id).collect();
+        let first = (!self.card_list.items.is_empty()).then_some(0);
+        self.card_list.state.select(first);
+    }
+
+    fn toggle_leech_list(&mut self, cache: &mut CardCache) {
+        self.leech_list = !self.leech_list;
+        self.filter = if self.leech_list {
+            FilterUtil {
+                tags: vec![LEECH_TAG.to_string()],
+                ..Default::default()
+            }
+            .into()
+        } else {
+            Query::default()
+        };
+        self.update_list(cache);
+    }
+
+    fn run_query(&mut self, cache: &mut CardCache) {
+        match parse_query(&self.query.get_text()) {
+            Ok(filter) => {
+                self.filter = filter;
+                self.leech_list = false;
+                self.update_list(cache);
+                self.query_status = format!("{} cards match", self.card_list.items.len());
+
+                if self.filter.uses_date(DateField::Created) && !creation_times_known(cache) {
+                    self.query_status.push_str(
+                        "\nsome cards have no creation time, they never match created filters",
+                    );
+                }
+            }
+            Err(e) => self.query_status = format!("{e}\n\n{QUERY_HELP}"),
         }
     }
 
@@ -82,6 +238,20 @@ impl Browser<'_> {
             .filter
             .evaluate_cards(cards, &mut cache.inner.lock().unwrap());
         self.card_list = StatefulList::with_items(filtered);
+        self.sort_cards(cache);
+    }
+
+    /// Updates the list after cards were changed or deleted, the marks of the cards that are
+    /// still listed are kept.
+    fn refresh_list(&mut self, cache: &mut CardCache) {
+        let marked = std::mem::take(&mut self.card_list.marked);
+        self.update_list(cache);
+        self.cache_len = cache.card_qty();
+        self.card_list.marked = marked
+            .into_iter()
+            .filter(|card| cache.try_get_ref(*card).is_some())
+            .filter(|card| self.card_list.items.contains(card))
+            .collect();
     }
 }
 
@@ -90,9 +260,23 @@ impl Tab for Browser<'_> {
     type ReturnType = ReturnType;
 
     fn handle_popup_value(&mut self, cache: &mut Self::AppState, filter: ReturnType) {
-        if let ReturnType::Filter(filter) = filter {
-            self.filter = filter.clone();
-            self.update_list(cache);
+        match filter {
+            ReturnType::Filter(filter) => {
+                self.filter = filter.clone();
+                self.update_list(cache);
+            }
+            ReturnType::Search(search) => {
+                self.filter = search.query;
+                self.sort_by = search.sort_by;
+                self.then_by = search.then_by;
+                self.descending = search.descending;
+                self.leech_list = false;
+                self.query_status = format!("search '{}'", search.name);
+                self.update_sort_info();
+                self.update_list(cache);
+            }
+            ReturnType::NoOp => self.refresh_list(cache),
+            _ => {}
         }
     }
 
@@ -110,18 +294,22 @@ impl Tab for Browser<'_> {
         let (front, back) = vsplit2(sides, 50, 50);
         let (filter, mune) = split_off(
             filter,
-            self.sort_choice.len() as u16 + 2,
+            self.sort_choice.len() as u16 + 3,
             mischef::Retning::Down,
         );
+        let (sort_info, mune) = split_off(mune, 1, mischef::Retning::Up);
+        let (query, filter) = split_off(filter, 3, mischef::Retning::Up);
         let (dpy, dpt) = vsplit2(deps, 50, 50);
 
         vec![
             (&mut self.info, info),
+            (&mut self.query, query),
             (&mut self.filter_input, filter),
             (&mut self.front_card, front),
             (&mut self.back_card, back),
             (&mut self.card_list, list),
             (&mut self.sort_choice, mune),
+            (&mut self.sort_info, sort_info),
             (&mut self.dependencies, dpy),
             (&mut self.dependents, dpt),
         ]
@@ -133,20 +321,31 @@ impl Tab for Browser<'_> {
 
     fn pre_render_hook(&mut self, cache: &mut Self::AppState) {
         if cache.card_qty() != self.cache_len {
-            self.update_list(cache);
-            self.cache_len = cache.card_qty();
+            self.refresh_list(cache);
         }
     }
 
     fn tab_keyhandler_deselected(
         &mut self,
-        _cache: &mut Self::AppState,
+        cache: &mut Self::AppState,
         key: crossterm::event::KeyEvent,
     ) -> bool {
         if key.code == KeyCode::Char('/') {
             self.tab_data.is_selected = true;
-            self.move_to_id(self.filter_input.id().as_str());
-            self.filter_input.inner.state.select(Some(0));
+            self.move_to_id(self.query.id().as_str());
+            false
+        } else if key.code == KeyCode::Char('L') {
+            self.toggle_leech_list(cache);
+            false
+        } else if key.code == KeyCode::Char('S') {
+            let current = SavedSearch {
+                name: String::new(),
+                query: self.filter.clone(),
+                sort_by: self.sort_by,
+                then_by: self.then_by,
+                descending: self.descending,
+            };
+            self.set_popup(Box::new(SearchChoice::new(Some(current))));
             false
         } else {
             true
@@ -158,40 +357,31 @@ impl Tab for Browser<'_> {
         cache: &mut Self::AppState,
         key: crossterm::event::KeyEvent,
     ) -> bool {
-        if self.is_selected(&self.filter_input) {
+        if self.is_selected(&self.query) {
+            if key.code == KeyCode::Enter {
+                self.run_query(cache);
+                return false;
+            }
+        } else if self.is_selected(&self.filter_input) {
             if self.filter_input.is_valid() && key.code == KeyCode::Enter {
-                self.filter = self.filter_input.extract_type();
+                self.filter = self.filter_input.extract_type().into();
                 let all_ids = cache.all_ids();
                 let filtered = self
                     .filter
                     .evaluate_cards(all_ids, &mut cache.inner.lock().unwrap());
                 self.card_list = StatefulList::with_items(filtered);
+                self.sort_cards(cache);
             }
         } else if self.is_selected(&self.sort_choice) {
-            if key.code == KeyCode::Enter {
-                match self.sort_choice.current_item() {
-                    Sorter::LastModified => self
-                        .card_list
-                        .items
-                        .sort_by_key(|id| cache.get_ref(*id).last_modified()),
-                    Sorter::RecallRate => self.card_list.items.sort_by_key(|id| {
-                        (cache.get_ref(*id).recall_rate().unwrap_or_default() * 100.) as u32
-                    }),
-                    Sorter::AlphaBetical => self
-                        .card_list
-                        .items
-                        .sort_by_key(|id| cache.get_ref(*id).front_text().to_string()),
-                    Sorter::Shuffled => {
-                        self.card_list.items.shuffle(&mut thread_rng());
-                    }
-                }
-                self.card_list.state.select(Some(0));
-
-                if self.sort_dir {
-                    self.card_list.items.reverse();
-                }
-                self.sort_dir = !self.sort_dir;
+            match key.code {
+                KeyCode::Enter => self.sort_by = Some(self.sort_choice.current_item()),
+                KeyCode::Char('b') => self.then_by = Some(self.sort_choice.current_item()),
+                KeyCode::Char('d') => self.descending = !self.descending,
+                _ => return true,
             }
+
+            self.update_sort_info();
+            self.sort_cards(cache);
         } else if self.is_selected(&self.card_list) {
             if key.code == KeyCode::Enter {
                 let selected = self.card_list.selected();
@@ -205,6 +395,23 @@ impl Tab for Browser<'_> {
                 }
             }
 
+            if self.leech_list && key.code == KeyCode::Char('u') {
+                if let Some(card) = self.selected_card() {
+                    unmark_leech(card, cache);
+                    self.update_list(cache);
+                }
+            }
+
+            match key.code {
+                KeyCode::Char('m') => {
+                    self.card_list.toggle_mark();
+                    self.card_list.next();
+                }
+                KeyCode::Char('M') => self.card_list.mark_all(),
+                KeyCode::Char('i') => self.card_list.invert_marks(),
+                _ => {}
+            }
+
             if key.code == KeyCode::Char('c') {
                 let cards = self.selected_cards();
                 let x = ActionPicker::new(cards);
@@ -213,8 +420,17 @@ impl Tab for Browser<'_> {
 
             if let KeyCode::Char(c) = key.code {
                 if let Ok(action) = CardAction::from_char(c.to_string().as_str()) {
-                    if let Some(card) = self.selected_card() {
-                        self.evaluate(card, cache, action);
+                    let cards = self.selected_cards();
+                    // A popup opened from the picker would be closed along with it, so these are
+                    // for the card under the cursor only.
+                    if action.opens_popup() {
+                        if let Some(card) = self.selected_card() {
+                            self.evaluate(card, cache, action);
+                        }
+                    } else if cards.len() > 1 {
+                        self.set_popup(Box::new(ActionPicker::confirm(cards, action)));
+                    } else {
+                        self.evaluate_current(cache, action);
                     }
                 }
             }
@@ -228,6 +444,7 @@ impl Tab for Browser<'_> {
         self.back_card.text.clear();
         self.dependencies.tree = StatefulTree::with_items(vec![]);
         self.dependents.tree = StatefulTree::with_items(vec![]);
+        self.info.text = self.query_status.clone();
 
         if let Some(card_id) = self.card_list.selected() {
             let card_id = *card_id;
@@ -235,7 +452,7 @@ impl Tab for Browser<'_> {
                 return;
             };
 
-            self.front_card.text = card.front_text().to_owned();
+            self.front_card.text = front_display(&card);
             self.back_card.text = card.back_text().to_owned();
             self.dependencies
                 .replace_items(card_dependencies(card_id, cache));
@@ -243,7 +460,20 @@ impl Tab for Browser<'_> {
             self.dependents
                 .replace_items(card_dependents(card_id, cache));
 
-            self.info.text = "".into();
+            let card_info = if self.leech_list {
+                format!(
+                    "leech, {} lapses in a row\npress 'u' once it's fixed",
+                    card.lapses()
+                )
+            } else if !self.card_list.marked.is_empty() {
+                format!("{} marked", self.card_list.marked.len())
+            } else {
+                "".into()
+            };
+
+            if !card_info.is_empty() {
+                self.info.text = format!("{card_info}\n{}", self.info.text);
+            }
         }
     }
 
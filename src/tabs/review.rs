@@ -1,27 +1,44 @@
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
 
 use crossterm::event::KeyCode;
 use rand::seq::SliceRandom;
-use speki_backend::{cache::Cards, filter::FilterUtil, Id};
+use speki_backend::{cache::Cards, categories::Category, filter::FilterUtil, Id};
 
 use mischef::{Tab, TabData, Widget};
+use vedvaring::SingletonPersist;
 
 use ratatui::prelude::*;
+use strum::IntoEnumIterator;
 use strum_macros::{EnumIter, EnumString};
 
-use crate::{popups::CardReviewer, widgets::enum_choice::EnumChoice, CardCache, ReturnType};
+use crate::{
+    popups::{days_until_due, CardReviewer, CatChoice, FilterChoice, ReviewSession, SearchChoice},
+    settings::{Allowance, QueueOrder, Settings, Thresholds},
+    utils::{dependencies_first, is_leech, TextDisplay},
+    vsplit2,
+    widgets::enum_choice::EnumChoice,
+    CardCache, ReturnType,
+};
+
+/// How many random cards to pick for a cram session.
+const CRAM_QTY: usize = 50;
 
 // Like review filter but all of the dependencies have to be strong memories
-fn confident_filter() -> FilterUtil {
+fn confident_filter(thresholds: &Thresholds) -> FilterUtil {
     let dependencies = FilterUtil {
-        min_recall_rate: Some(0.95),
-        min_stability: Some(Duration::from_secs(86400)),
+        min_recall_rate: Some(thresholds.dependency_min_recall_rate),
+        min_stability: Some(Duration::from_secs_f32(
+            thresholds.dependency_min_stability_days * 86400.,
+        )),
         ..FilterUtil::new_valid()
     };
 
     FilterUtil {
         all_dependencies: Some(Box::new(dependencies)),
-        max_recall_rate: Some(0.9),
+        max_recall_rate: Some(thresholds.max_recall_rate),
         ..FilterUtil::new_valid()
     }
 }
@@ -29,19 +46,313 @@ fn confident_filter() -> FilterUtil {
 #[derive(EnumString, EnumIter, strum_macros::Display)]
 enum MenuChoice {
     Review,
+    ResumeSession,
+    NewCards,
+    Overdue,
+    Leeches,
+    Category,
+    RandomCram,
+    CramCategory,
+    CramFilter,
+    CustomFilter,
+    SavedSearch,
+}
+
+impl MenuChoice {
+    /// Modes that drill the cards without affecting their scheduling.
+    fn is_cram(&self) -> bool {
+        matches!(
+            self,
+            MenuChoice::RandomCram | MenuChoice::CramCategory | MenuChoice::CramFilter
+        )
+    }
+}
+
+/// The regular review, cards that are due and whose dependencies are known well.
+fn review_cards(cache: &mut CardCache, settings: &Settings) -> Vec<Id> {
+    due_cards(cache.all_ids(), cache, settings)
+}
+
+/// Like the regular review, but only for cards in the given category.
+fn category_cards(category: Category, cache: &mut CardCache, settings: &Settings) -> Vec<Id> {
+    let mut cards = cache.all_ids();
+    cards.retain(|card| cache.get_ref(*card).category() == &category);
+    due_cards(cards, cache, settings)
+}
+
+/// Filters the cards by the thresholds that apply to their category.
+fn due_cards(cards: Vec<Id>, cache: &mut CardCache, settings: &Settings) -> Vec<Id> {
+    let mut categories: BTreeMap<Category, Vec<Id>> = BTreeMap::new();
+    for card in cards {
+        let category = cache.get_ref(card).category().to_owned();
+        categories.entry(category).or_default().push(card);
+    }
+
+    let mut due = vec![];
+
+    for (category, cards) in categories {
+        let thresholds = settings.thresholds(&category);
+
+        let cards: Vec<Id> = Cards(cards.into_iter().collect())
+            .filter_importance(thresholds.min_importance, &mut cache.inner.lock().unwrap())
+            .0
+            .into_iter()
+            .collect();
+
+        let f = confident_filter(thresholds);
+
+        let mut cards = f.evaluate_cards(cards, &mut cache.inner.lock().unwrap());
+        cards.retain(|card| {
+            cache
+                .get_ref(*card)
+                .older_than(thresholds.min_days_since_review)
+        });
+        due.extend(cards);
+    }
+
+    due
+}
+
+/// One line per threshold, each starting with `indent`.
+fn threshold_values(t: &Thresholds, indent: &str) -> String {
+    format!(
+        "{indent}target recall rate: {}\n{indent}dependency min recall rate: {}\n{indent}dependency min stability: {} days\n{indent}min importance: {}\n{indent}min days since review: {}\n",
+        t.max_recall_rate,
+        t.dependency_min_recall_rate,
+        t.dependency_min_stability_days,
+        t.min_importance,
+        t.min_days_since_review
+    )
+}
+
+fn thresholds_text(settings: &Settings) -> String {
+    let mut text = threshold_values(&settings.thresholds, "");
+
+    for (category, thresholds) in &settings.category_thresholds {
+        text.push_str(&format!("overridden in {category}:\n"));
+        text.push_str(&threshold_values(thresholds, "  "));
+    }
+
+    let type_answers = if settings.type_answers { "on" } else { "off" };
+    text.push_str(&format!("\ntype-in answers: {type_answers}\n"));
+
+    let suspend = if settings.suspend_leeches {
+        ", then suspended"
+    } else {
+        ""
+    };
+    text.push_str(&format!(
+        "leech after {} lapses in a row{suspend}\n",
+        settings.leech_lapses
+    ));
+
+    if let Some(secs) = settings.time_limit_secs {
+        text.push_str(&format!("time limit: {secs}s\n"));
+    }
+    for (category, secs) in &settings.category_time_limits {
+        text.push_str(&format!("time limit in {category}: {secs}s\n"));
+    }
+
+    text.push_str("\nqueue order:\n");
+
+    for mode in MenuChoice::iter() {
+        if !matches!(mode, MenuChoice::ResumeSession) {
+            let order = settings.queue_order(&mode.to_string());
+            text.push_str(&format!("  {mode}: {order}\n"));
+        }
+    }
+
+    text.push_str("\npress 'o' to edit the settings, 't' to toggle type-in answers, 's' to change the queue order of the selected mode");
+    text
+}
+
+/// Sorts the cards in the order they should be reviewed in.
+fn order_cards(
+    mut cards: Vec<Id>,
+    order: QueueOrder,
+    cache: &mut CardCache,
+    settings: &Settings,
+) -> Vec<Id> {
+    // So that ties don't always come up in the same order.
+    cards.shuffle(&mut rand::thread_rng());
+
+    match order {
+        QueueOrder::Random => cards,
+        QueueOrder::LowestRecallRate => sort_by_score(cards, |card| {
+            cache.get_ref(card).recall_rate().unwrap_or_default()
+        }),
+        QueueOrder::HighestImportance => sort_by_score(cards, |card| {
+            -cache
+                .get_ref(card)
+                .weighted_importance(&mut cache.inner.lock().unwrap())
+        }),
+        QueueOrder::Dependencies => dependencies_first(cards, cache),
+        QueueOrder::InterleavedCategories => interleave_categories(cards, cache),
+        QueueOrder::OldestDue => sort_by_score(cards, |card| {
+            let card = cache.get_ref(card);
+            let target = settings.thresholds(card.category()).max_recall_rate;
+            let since_review = card.time_since_last_review();
+
+            match (days_until_due(card.stability(), target), since_review) {
+                (Some(due), Some(since_review)) => due - since_review.as_secs_f32() / 86400.,
+                // Cards that were never reviewed have never been due.
+                _ => f32::MAX,
+            }
+        }),
+    }
+}
+
+/// Lowest score first.
+fn sort_by_score(cards: Vec<Id>, mut score: impl FnMut(Id) -> f32) -> Vec<Id> {
+    let mut scored: Vec<(f32, Id)> = cards.into_iter().map(|card| (score(card), card)).collect();
+    scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+    scored.into_iter().map(|(_, card)| card).collect()
+}
+
+fn interleave_categories(cards: Vec<Id>, cache: &mut CardCache) -> Vec<Id> {
+    let mut categories: BTreeMap<Category, VecDeque<Id>> = BTreeMap::new();
+    for card in cards {
+        let category = cache.get_ref(card).category().to_owned();
+        categories.entry(category).or_default().push_back(card);
+    }
+
+    let mut interleaved = vec![];
+    while !categories.is_empty() {
+        categories.retain(|_, cards| {
+            interleaved.extend(cards.pop_front());
+            !cards.is_empty()
+        });
+    }
+
+    interleaved
+}
+
+fn valid_cards(cache: &mut CardCache) -> Vec<Id> {
+    FilterUtil::new_valid().evaluate_cards(cache.all_ids(), &mut cache.inner.lock().unwrap())
+}
+
+fn new_cards(cache: &mut CardCache) -> Vec<Id> {
+    let mut cards = valid_cards(cache);
+    cards.retain(|card| cache.get_ref(*card).reviews().is_empty());
+    cards
+}
+
+fn overdue_cards(cache: &mut CardCache, settings: &Settings) -> Vec<Id> {
+    let filter = FilterUtil {
+        max_recall_rate: Some(settings.thresholds.max_recall_rate),
+        ..FilterUtil::new_valid()
+    };
+
+    filter.evaluate_cards(cache.all_ids(), &mut cache.inner.lock().unwrap())
+}
+
+fn leech_cards(cache: &mut CardCache) -> Vec<Id> {
+    let mut cards = valid_cards(cache);
+    cards.retain(|card| is_leech(*card, cache));
+    cards
+}
+
+fn cram_cards(cache: &mut CardCache) -> Vec<Id> {
+    let mut cards = valid_cards(cache);
+    cards.shuffle(&mut rand::thread_rng());
+    cards.truncate(CRAM_QTY);
+    cards
 }
 
 pub struct ReviewMenu {
     option: EnumChoice<MenuChoice>,
+    thresholds: TextDisplay,
+    settings: Settings,
+    /// A review that would replace the unfinished session, waiting to be confirmed.
+    pending: Option<(bool, Vec<Id>)>,
     pub tab_data: TabData<CardCache, ReturnType>,
 }
 
 impl ReviewMenu {
     pub fn new() -> Self {
-        Self {
+        let mut s = Self {
             option: EnumChoice::<MenuChoice>::new(),
+            thresholds: TextDisplay::default(),
+            settings: Settings::default(),
+            pending: None,
             tab_data: TabData::default(),
+        };
+        s.reload_settings();
+        s
+    }
+
+    /// The settings file might have been edited since last time.
+    fn reload_settings(&mut self) {
+        let mut text = String::new();
+        self.settings = match Settings::try_load() {
+            Ok(settings) => settings,
+            Err(e) => {
+                text.push_str(&format!(
+                    "the settings file has an error, using the defaults until it's fixed:\n{e}\n\n"
+                ));
+                Settings::default()
+            }
+        };
+        text.push_str(&thresholds_text(&self.settings));
+        text.push('\n');
+        text.push_str(&Allowance::new(&self.settings).text());
+
+        let session = ReviewSession::load();
+        if !session.is_empty() {
+            text.push_str(&format!(
+                "\n\nunfinished session: {} cards left",
+                session.unfinished_qty()
+            ));
+        }
+
+        self.thresholds = TextDisplay::new(text);
+    }
+
+    fn start_review(&mut self, mode: MenuChoice, cards: Vec<Id>, cache: &mut CardCache) {
+        let order = self.settings.queue_order(&mode.to_string());
+        let mut cards = order_cards(cards, order, cache, &self.settings);
+
+        // After ordering, so that the limits cut off the cards at the end of the queue. Cramming
+        // doesn't count as reviews, so it isn't limited.
+        if !mode.is_cram() {
+            let mut allowance = Allowance::new(&self.settings);
+            cards.retain(|card| {
+                let card = cache.get_ref(*card);
+                allowance.take(card.category(), card.reviews().is_empty())
+            });
         }
+        // The reviewer takes the cards from the back.
+        cards.reverse();
+
+        let session = ReviewSession::load();
+        if session.is_empty() {
+            self.open_review(mode.is_cram(), cards, cache);
+            return;
+        }
+
+        self.thresholds = TextDisplay::new(format!(
+            "there's an unfinished session with {} cards left, starting this review replaces it\n\ny: start anyway    any other key: keep the session",
+            session.unfinished_qty()
+        ));
+        self.pending = Some((mode.is_cram(), cards));
+    }
+
+    fn open_review(&mut self, cram: bool, cards: Vec<Id>, cache: &mut CardCache) {
+        let rev = CardReviewer::saved(cards, cram, cache);
+        self.set_popup(Box::new(rev));
+    }
+
+    /// Returns whether the key was used to answer the pending review.
+    fn answer_pending(&mut self, cache: &mut CardCache, key: KeyCode) -> bool {
+        let Some((cram, cards)) = self.pending.take() else {
+            return false;
+        };
+
+        if key == KeyCode::Char('y') {
+            self.open_review(cram, cards, cache);
+        }
+        self.reload_settings();
+        true
     }
 }
 
@@ -53,8 +364,34 @@ impl Tab for ReviewMenu {
         &self.tab_data
     }
 
+    fn handle_popup_value(&mut self, cache: &mut Self::AppState, value: ReturnType) {
+        // The menu still has the mode that opened the popup selected.
+        let mode = self.option.current_item();
+        let cards = match value {
+            ReturnType::Category(category) if mode.is_cram() => {
+                let mut cards = valid_cards(cache);
+                cards.retain(|card| cache.get_ref(*card).category() == &category);
+                cards
+            }
+            ReturnType::Category(category) => category_cards(category, cache, &self.settings),
+            ReturnType::Filter(filter) => {
+                filter.evaluate_cards(cache.all_ids(), &mut cache.inner.lock().unwrap())
+            }
+            ReturnType::Search(search) => search
+                .query
+                .evaluate_cards(cache.all_ids(), &mut cache.inner.lock().unwrap()),
+            _ => return,
+        };
+
+        self.start_review(mode, cards, cache);
+    }
+
     fn widgets(&mut self, area: Rect) -> Vec<(&mut dyn Widget<AppData = Self::AppState>, Rect)> {
-        vec![(&mut self.option, area)]
+        let (option, thresholds) = vsplit2(area, 50, 50);
+        vec![
+            (&mut self.option, option),
+            (&mut self.thresholds, thresholds),
+        ]
     }
 
     fn tabdata(&mut self) -> &mut TabData<Self::AppState, Self::ReturnType> {
@@ -66,31 +403,79 @@ impl Tab for ReviewMenu {
         cache: &mut Self::AppState,
         key: crossterm::event::KeyEvent,
     ) -> bool {
-        if self.is_selected(&self.option) && key == KeyCode::Enter.into() {
-            match self.option.current_item() {
-                MenuChoice::Review => {
-                    let cards = Cards(cache.all_ids().into_iter().collect());
-                    let cards: Vec<Id> = cards
-                        .filter_importance(1., &mut cache.inner.lock().unwrap())
-                        .0
-                        .into_iter()
-                        .collect();
+        if self.answer_pending(cache, key.code) {
+            return false;
+        }
 
-                    let f = confident_filter();
+        if key.code == KeyCode::Char('o') {
+            Settings::open();
+            return false;
+        }
+
+        // Saving over a settings file with an error would throw away what's in it.
+        if key.code == KeyCode::Char('t') {
+            if let Ok(mut settings) = Settings::try_load() {
+                settings.type_answers = !settings.type_answers;
+                settings.save();
+            }
+            self.reload_settings();
+            return false;
+        }
 
-                    let mut cards = f.evaluate_cards(cards, &mut cache.inner.lock().unwrap());
-                    cards.retain(|card| cache.get_ref(*card).older_than(1.0));
+        if key.code == KeyCode::Char('s') {
+            if let Ok(mut settings) = Settings::try_load() {
+                settings.cycle_queue_order(&self.option.current_item().to_string());
+                settings.save();
+            }
+            self.reload_settings();
+            return false;
+        }
 
-                    //cards.shuffle(&mut rand::thread_rng());
+        if self.is_selected(&self.option) && key == KeyCode::Enter.into() {
+            self.reload_settings();
 
-                    let rev = CardReviewer::new(cards, cache);
-                    self.set_popup(Box::new(rev));
+            let mode = self.option.current_item();
+            let cards = match mode {
+                MenuChoice::Review => review_cards(cache, &self.settings),
+                MenuChoice::NewCards => new_cards(cache),
+                MenuChoice::Overdue => overdue_cards(cache, &self.settings),
+                MenuChoice::Leeches => leech_cards(cache),
+                MenuChoice::RandomCram => cram_cards(cache),
+                MenuChoice::ResumeSession => {
+                    let session = ReviewSession::load();
+                    if !session.is_empty() {
+                        let rev = CardReviewer::resume(session, cache);
+                        self.set_popup(Box::new(rev));
+                    }
+                    return true;
+                }
+                MenuChoice::Category | MenuChoice::CramCategory => {
+                    self.set_popup(Box::new(CatChoice::new()));
+                    return true;
+                }
+                MenuChoice::CustomFilter | MenuChoice::CramFilter => {
+                    self.set_popup(Box::new(FilterChoice::new(cache)));
+                    return true;
+                }
+                MenuChoice::SavedSearch => {
+                    self.set_popup(Box::new(SearchChoice::new(None)));
+                    return true;
                 }
             };
+
+            self.start_review(mode, cards, cache);
         }
         true
     }
 
+    fn tab_keyhandler_deselected(
+        &mut self,
+        cache: &mut Self::AppState,
+        key: crossterm::event::KeyEvent,
+    ) -> bool {
+        !self.answer_pending(cache, key.code)
+    }
+
     fn title(&self) -> &str {
         "review"
     }
@@ -1,9 +1,21 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, time::Duration};
 
 use mischef::{Tab, TabData};
-use speki_backend::common::duration_to_days;
+use speki_backend::{common::duration_to_days, Id};
+use vedvaring::SingletonPersist;
 
-use crate::{utils::TextDisplay, CardCache, ReturnType};
+use crate::{
+    popups::{RecallLog, SkipLog},
+    utils::{front_display, TextDisplay},
+    CardCache, ReturnType,
+};
+
+/// Successful recalls that took longer than this, from showing the front until revealing
+/// the back, are probably not as solid as the grade suggests.
+const SLOW_RECALL: Duration = Duration::from_secs(10);
+
+/// Cards skipped at least this many times are probably badly written.
+const OFTEN_SKIPPED: usize = 3;
 
 pub struct Stats {
     tab_data: TabData<CardCache, ReturnType>,
@@ -18,10 +30,21 @@ impl Stats {
         let ids = cache.all_ids();
         let mut workload = 0.;
         let mut map: BTreeMap<u32, Vec<f32>> = BTreeMap::default();
+        let mut slow_recalls = vec![];
+        let recalls = RecallLog::load().latest();
 
         for id in ids {
             let card = cache.get_ref(id);
 
+            let last_review = card.reviews().last();
+            // Reviews from before recall times were logged don't have one.
+            if let (Some(review), Some(recall)) = (last_review, recalls.get(&id)) {
+                let recall_time = Duration::from_secs_f32(recall.recall_secs);
+                if review.grade.is_succesful_recall() && recall_time > SLOW_RECALL {
+                    slow_recalls.push((recall_time, front_display(&card)));
+                }
+            }
+
             for (recall_rate, outcome) in card.the_review().grade_and_chance() {
                 let recall_rate = (recall_rate * 100.) as u32;
                 let outcome = (outcome as u32) as f32;
@@ -59,11 +82,46 @@ impl Stats {
             new_map.insert(*k, avg);
         }
 
+        // Reviews from before response times were recorded have a zero duration.
+        let review_times: Vec<f32> = reviews
+            .iter()
+            .map(|review| review.time_spent.as_secs_f32())
+            .filter(|secs| *secs > 0.)
+            .collect();
+        let avg_review_time = if review_times.is_empty() {
+            0.
+        } else {
+            review_times.iter().sum::<f32>() / review_times.len() as f32
+        };
+
         let reviews = reviews.len();
         let cards = cache.card_qty();
 
         let mut text =
-            format!("amount of reviews: {reviews}\ndaily cards: {daily_cards}\ntot str: {tot_str}\nworkload: {workload}\ntot cards: {cards}\n");
+            format!("amount of reviews: {reviews}\ndaily cards: {daily_cards}\ntot str: {tot_str}\nworkload: {workload}\ntot cards: {cards}\navg time per card: {avg_review_time:.1}s\n");
+
+        slow_recalls.sort_by_key(|(time, _)| *time);
+        slow_recalls.reverse();
+        text.push_str(&format!("slow recalls: {}\n", slow_recalls.len()));
+        for (time, front) in slow_recalls.iter().take(10) {
+            text.push_str(&format!("  {:.1}s  {}\n", time.as_secs_f32(), front));
+        }
+
+        let mut skipped: Vec<(usize, Id)> = SkipLog::load()
+            .counts()
+            .into_iter()
+            .filter(|(_, qty)| *qty >= OFTEN_SKIPPED)
+            .map(|(card, qty)| (qty, card))
+            .collect();
+        skipped.sort();
+        skipped.reverse();
+        text.push_str(&format!("often skipped: {}\n", skipped.len()));
+        for (qty, card) in skipped.iter().take(10) {
+            let Some(card) = cache.try_get_ref(*card) else {
+                continue;
+            };
+            text.push_str(&format!("  {qty}x  {}\n", front_display(&card)));
+        }
 
         for (k, v) in &new_map {
             if *k % 2 == 0 {
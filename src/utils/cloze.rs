@@ -0,0 +1,152 @@
+use std::collections::BTreeSet;
+
+use speki_backend::{saved_card::SavedCard, Id};
+
+/// A `{{c1::answer}}` or `{{c1::answer::hint}}` marker in the text of a cloze note.
+#[derive(Debug)]
+struct Deletion<'a> {
+    number: u32,
+    answer: &'a str,
+    hint: Option<&'a str>,
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Deletion(Deletion<'a>),
+}
+
+fn parse_deletion(inner: &str) -> Option<Deletion<'_>> {
+    let rest = inner.strip_prefix('c')?;
+    let (number, rest) = rest.split_once("::")?;
+    let number = number.parse().ok()?;
+    let (answer, hint) = match rest.split_once("::") {
+        Some((answer, hint)) => (answer, Some(hint)),
+        None => (rest, None),
+    };
+
+    Some(Deletion {
+        number,
+        answer,
+        hint,
+    })
+}
+
+fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{c") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+
+        match parse_deletion(&rest[start + 2..start + len]) {
+            Some(deletion) => {
+                segments.push(Segment::Text(&rest[..start]));
+                segments.push(Segment::Deletion(deletion));
+            }
+            // Not a valid marker, so keep it as it is.
+            None => segments.push(Segment::Text(&rest[..start + len + 2])),
+        }
+
+        rest = &rest[start + len + 2..];
+    }
+
+    segments.push(Segment::Text(rest));
+    segments
+}
+
+/// The cloze numbers used in the text, each one becomes its own card.
+pub fn cloze_numbers(text: &str) -> BTreeSet<u32> {
+    segments(text)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Deletion(deletion) => Some(deletion.number),
+            Segment::Text(_) => None,
+        })
+        .collect()
+}
+
+/// The text with the active deletions blanked out and the other ones filled in.
+pub fn render_cloze(text: &str, active: u32) -> String {
+    segments(text)
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => text.to_owned(),
+            Segment::Deletion(deletion) if deletion.number == active => {
+                format!("[{}]", deletion.hint.unwrap_or("..."))
+            }
+            Segment::Deletion(deletion) => deletion.answer.to_owned(),
+        })
+        .collect()
+}
+
+/// The answers of the active deletions.
+pub fn cloze_answer(text: &str, active: u32) -> String {
+    let answers: Vec<&str> = segments(text)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Deletion(deletion) if deletion.number == active => Some(deletion.answer),
+            _ => None,
+        })
+        .collect();
+
+    answers.join(", ")
+}
+
+/// Tag that marks which of the clozes in the note a card is for.
+pub fn cloze_tag(number: u32) -> String {
+    format!("cloze{number}")
+}
+
+/// Tag shared by the cards made from the same cloze note, named after the first of them.
+pub fn note_tag(first_card: Id) -> String {
+    format!("note-{first_card}")
+}
+
+/// The cloze number that the card is for, if it's a cloze card.
+pub fn active_cloze(card: &SavedCard) -> Option<u32> {
+    cloze_numbers(card.front_text())
+        .into_iter()
+        .find(|number| card.contains_tag(&cloze_tag(*number)))
+}
+
+/// What to show on the front of a card, cloze cards have their active deletion blanked out.
+pub fn front_display(card: &SavedCard) -> String {
+    match active_cloze(card) {
+        Some(number) => render_cloze(card.front_text(), number),
+        None => card.front_text().to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str = "{{c1::Rust}} was first released in {{c2::2015::year}} by {{c1::Mozilla}}";
+
+    #[test]
+    fn numbers() {
+        assert_eq!(cloze_numbers(NOTE), BTreeSet::from([1, 2]));
+        assert!(cloze_numbers("no clozes {{here}}").is_empty());
+    }
+
+    #[test]
+    fn render() {
+        assert_eq!(
+            render_cloze(NOTE, 1),
+            "[...] was first released in 2015 by [...]"
+        );
+        assert_eq!(
+            render_cloze(NOTE, 2),
+            "Rust was first released in [year] by Mozilla"
+        );
+        assert_eq!(render_cloze("keep {{c}} as is", 1), "keep {{c}} as is");
+    }
+
+    #[test]
+    fn answer() {
+        assert_eq!(cloze_answer(NOTE, 1), "Rust, Mozilla");
+        assert_eq!(cloze_answer(NOTE, 2), "2015");
+    }
+}
@@ -0,0 +1,148 @@
+use mischef::Widget;
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Paragraph, Wrap},
+    Frame,
+};
+
+use crate::CardCache;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diff {
+    /// In both the typed answer and the correct one.
+    Same,
+    /// Only in the correct answer.
+    Missing,
+    /// Only in the typed answer.
+    Extra,
+}
+
+/// Character level diff between a typed answer and the correct one, based on the
+/// longest common subsequence.
+pub fn char_diff(typed: &str, correct: &str) -> Vec<(Diff, char)> {
+    let typed: Vec<char> = typed.chars().collect();
+    let correct: Vec<char> = correct.chars().collect();
+
+    // lcs[i][j] is the length of the longest common subsequence of typed[i..] and correct[j..]
+    let mut lcs = vec![vec![0; correct.len() + 1]; typed.len() + 1];
+    for i in (0..typed.len()).rev() {
+        for j in (0..correct.len()).rev() {
+            lcs[i][j] = if typed[i] == correct[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::with_capacity(typed.len().max(correct.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < typed.len() && j < correct.len() {
+        if typed[i] == correct[j] {
+            diff.push((Diff::Same, typed[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            diff.push((Diff::Extra, typed[i]));
+            i += 1;
+        } else {
+            diff.push((Diff::Missing, correct[j]));
+            j += 1;
+        }
+    }
+
+    diff.extend(typed[i..].iter().map(|c| (Diff::Extra, *c)));
+    diff.extend(correct[j..].iter().map(|c| (Diff::Missing, *c)));
+    diff
+}
+
+/// How close the typed answer is to the correct one, from 0 to 1.
+pub fn similarity(diff: &[(Diff, char)]) -> f32 {
+    let same = diff.iter().filter(|(d, _)| *d == Diff::Same).count();
+    let typed = diff.iter().filter(|(d, _)| *d != Diff::Missing).count();
+    let correct = diff.iter().filter(|(d, _)| *d != Diff::Extra).count();
+
+    if typed + correct == 0 {
+        return 1.;
+    }
+
+    (2 * same) as f32 / (typed + correct) as f32
+}
+
+#[derive(Default, Debug)]
+pub struct DiffDisplay {
+    pub diff: Vec<(Diff, char)>,
+    pub footer: String,
+}
+
+impl Widget for DiffDisplay {
+    type AppData = CardCache;
+
+    fn keyhandler(&mut self, _cache: &mut CardCache, _key: crossterm::event::KeyEvent) {}
+
+    fn render(&mut self, f: &mut Frame, _cache: &mut CardCache, area: ratatui::layout::Rect) {
+        let mut lines = vec![Line::default()];
+
+        for (diff, c) in &self.diff {
+            if *c == '\n' {
+                lines.push(Line::default());
+                continue;
+            }
+
+            let style = match diff {
+                Diff::Same => Style::default().fg(Color::Green),
+                Diff::Missing => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                Diff::Extra => Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::CROSSED_OUT),
+            };
+
+            lines
+                .last_mut()
+                .unwrap()
+                .spans
+                .push(Span::styled(c.to_string(), style));
+        }
+
+        lines.push(Line::default());
+        lines.push(Line::from(self.footer.clone()));
+
+        f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }), area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn as_string(diff: &[(Diff, char)], kind: Diff) -> String {
+        diff.iter()
+            .filter(|(d, _)| *d == kind)
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    #[test]
+    fn diff_typo() {
+        let diff = char_diff("recieve", "receive");
+        assert_eq!(as_string(&diff, Diff::Same).len(), 6);
+        assert_eq!(as_string(&diff, Diff::Missing).len(), 1);
+        assert_eq!(as_string(&diff, Diff::Extra).len(), 1);
+    }
+
+    #[test]
+    fn diff_missing_end() {
+        let diff = char_diff("git", "git add");
+        assert_eq!(as_string(&diff, Diff::Same), "git");
+        assert_eq!(as_string(&diff, Diff::Missing), " add");
+        assert!(as_string(&diff, Diff::Extra).is_empty());
+    }
+
+    #[test]
+    fn similarity_bounds() {
+        assert_eq!(similarity(&char_diff("abc", "abc")), 1.);
+        assert_eq!(similarity(&char_diff("abc", "xyz")), 0.);
+        assert_eq!(similarity(&char_diff("", "")), 1.);
+    }
+}
@@ -0,0 +1,81 @@
+use speki_backend::{
+    card::{Card, IsSuspended},
+    Id,
+};
+
+use crate::{settings::Settings, CardCache};
+
+/// Cards that keep getting forgotten are tagged with this.
+pub const LEECH_TAG: &str = "leech";
+
+/// Leeches that got suspended for being leeches, as opposed to being suspended by hand.
+pub const LEECH_SUSPENDED_TAG: &str = "leech-suspended";
+
+pub fn is_leech(card: Id, cache: &CardCache) -> bool {
+    cache.get_ref(card).contains_tag(LEECH_TAG)
+}
+
+/// Tags the card as a leech if it has lapsed too many times in a row, and suspends it if
+/// the settings say so. Returns whether the card just became a leech.
+pub fn mark_leech(card: Id, cache: &mut CardCache, settings: &Settings) -> bool {
+    let saved = cache.get_ref(card);
+    if saved.contains_tag(LEECH_TAG) || saved.lapses() < settings.leech_lapses {
+        return false;
+    }
+
+    cache.edit_card(card, |card| tag_leech(card, settings.suspend_leeches));
+    true
+}
+
+/// For when the leech has been fixed, or the review that made it one got undone.
+pub fn unmark_leech(card: Id, cache: &mut CardCache) {
+    cache.edit_card(card, untag_leech);
+}
+
+fn tag_leech(card: &mut Card, suspend: bool) {
+    card.meta.tags.insert(LEECH_TAG.to_string());
+    if suspend && card.meta.suspended != IsSuspended::True {
+        card.meta.tags.insert(LEECH_SUSPENDED_TAG.to_string());
+        card.meta.suspended = IsSuspended::True;
+    }
+}
+
+/// Only unsuspends the card if it was the leech marking that suspended it.
+fn untag_leech(card: &mut Card) {
+    card.meta.tags.remove(LEECH_TAG);
+    if card.meta.tags.remove(LEECH_SUSPENDED_TAG) {
+        card.meta.suspended = IsSuspended::False;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmark_round_trip() {
+        let mut card = Card::default();
+        tag_leech(&mut card, true);
+        assert!(card.meta.tags.contains(LEECH_TAG));
+        assert_eq!(card.meta.suspended, IsSuspended::True);
+
+        untag_leech(&mut card);
+        assert!(card.meta.tags.is_empty());
+        assert_eq!(card.meta.suspended, IsSuspended::False);
+    }
+
+    #[test]
+    fn keeps_suspension_by_hand() {
+        let mut card = Card::default();
+        card.meta.suspended = IsSuspended::True;
+
+        tag_leech(&mut card, false);
+        untag_leech(&mut card);
+        assert!(card.meta.tags.is_empty());
+        assert_eq!(card.meta.suspended, IsSuspended::True);
+
+        tag_leech(&mut card, true);
+        untag_leech(&mut card);
+        assert_eq!(card.meta.suspended, IsSuspended::True);
+    }
+}
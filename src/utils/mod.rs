@@ -1,4 +1,9 @@
-use std::{io::Read, path::Path};
+use std::{
+    collections::BTreeSet,
+    io::Read,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
 
 use crossterm::event::KeyCode;
 use ratatui::{
@@ -8,7 +13,7 @@ use ratatui::{
     widgets::{List, ListItem, ListState},
     Frame,
 };
-use speki_backend::Id;
+use speki_backend::{filter::FilterUtil, saved_card::SavedCard, Id};
 
 use tui_tree_widget::{Tree, TreeItem, TreeState};
 
@@ -20,6 +25,18 @@ pub use text_input::*;
 mod text_display;
 pub use text_display::*;
 
+mod diff;
+pub use diff::*;
+
+mod cloze;
+pub use cloze::*;
+
+mod leech;
+pub use leech::*;
+
+mod query;
+pub use query::*;
+
 use crate::CardCache;
 
 fn card_dependent_inner(card: Id, cache: &mut CardCache) -> TreeItem<'static, Id> {
@@ -32,7 +49,7 @@ fn card_dependent_inner(card: Id, cache: &mut CardCache) -> TreeItem<'static, Id
     }
 
     // Create a tree item for the current directory.
-    TreeItem::new(card, cache.get_ref(card).front_text().to_string(), children).unwrap()
+    TreeItem::new(card, front_display(&cache.get_ref(card)), children).unwrap()
 }
 
 fn card_dependencies_inner(card: Id, cache: &mut CardCache) -> TreeItem<'static, Id> {
@@ -45,7 +62,7 @@ fn card_dependencies_inner(card: Id, cache: &mut CardCache) -> TreeItem<'static,
     }
 
     // Create a tree item for the current directory.
-    TreeItem::new(card, cache.get_ref(card).front_text().to_string(), children).unwrap()
+    TreeItem::new(card, front_display(&cache.get_ref(card)), children).unwrap()
 }
 
 pub fn card_dependents(card: Id, cache: &mut CardCache) -> Vec<TreeItem<'static, Id>> {
@@ -71,6 +88,77 @@ pub fn card_dependencies(card: Id, cache: &mut CardCache) -> Vec<TreeItem<'stati
     vec
 }
 
+/// The card with all of its dependencies, every card after its own dependencies. Suspended
+/// and unfinished dependencies are left out, as they can't be reviewed.
+pub fn prerequisites(card: Id, cache: &mut CardCache) -> Vec<Id> {
+    let mut ordered = vec![];
+    visit_dependencies(card, None, &mut BTreeSet::new(), &mut ordered, cache);
+
+    let valid: BTreeSet<Id> = FilterUtil::new_valid()
+        .evaluate_cards(ordered.clone(), &mut cache.inner.lock().unwrap())
+        .into_iter()
+        .collect();
+    ordered.retain(|id| *id == card || valid.contains(id));
+    ordered
+}
+
+/// Orders the cards so that every card comes after its dependencies.
+pub fn dependencies_first(cards: Vec<Id>, cache: &mut CardCache) -> Vec<Id> {
+    let included: BTreeSet<Id> = cards.iter().copied().collect();
+    let mut visited = BTreeSet::new();
+    let mut ordered = Vec::with_capacity(cards.len());
+
+    for card in cards {
+        visit_dependencies(card, Some(&included), &mut visited, &mut ordered, cache);
+    }
+
+    ordered
+}
+
+/// Depth first, so that the dependencies are pushed before the card. Cards that aren't
+/// included are walked through too, as they can link two cards that are.
+fn visit_dependencies(
+    card: Id,
+    included: Option<&BTreeSet<Id>>,
+    visited: &mut BTreeSet<Id>,
+    ordered: &mut Vec<Id>,
+    cache: &mut CardCache,
+) {
+    if !visited.insert(card) {
+        return;
+    }
+
+    for dependency in cache.dependencies(card) {
+        visit_dependencies(dependency, included, visited, ordered, cache);
+    }
+
+    let is_included = match included {
+        Some(included) => included.contains(&card),
+        None => true,
+    };
+
+    if is_included {
+        ordered.push(card);
+    }
+}
+
+/// When the card file was made. The cards don't store it themselves, and not every
+/// filesystem records it, so it might not be known.
+pub fn created_time(card: &SavedCard) -> Option<Duration> {
+    std::fs::metadata(card.path())
+        .and_then(|meta| meta.created())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+}
+
+/// Whether `created_time` works for every card.
+pub fn creation_times_known(cache: &CardCache) -> bool {
+    cache
+        .all_ids()
+        .into_iter()
+        .all(|card| created_time(&cache.get_ref(card)).is_some())
+}
+
 #[derive(Debug)]
 pub struct StatefulTree<'a, T> {
     pub state: TreeState<T>,
@@ -191,6 +279,7 @@ impl<T: Default + Eq + Clone + PartialEq + std::hash::Hash> Widget for TreeWidge
 pub struct StatefulList<T> {
     pub state: ListState,
     pub items: Vec<T>,
+    pub marked: BTreeSet<T>,
 }
 
 impl<T> StatefulList<T> {
@@ -199,7 +288,11 @@ impl<T> StatefulList<T> {
         if !items.is_empty() {
             state.select(Some(0));
         }
-        StatefulList { state, items }
+        StatefulList {
+            state,
+            items,
+            marked: BTreeSet::new(),
+        }
     }
 
     pub fn next(&mut self) {
@@ -245,6 +338,40 @@ impl<T> StatefulList<T> {
     }
 }
 
+impl<T: Ord + Clone> StatefulList<T> {
+    pub fn toggle_mark(&mut self) {
+        let Some(item) = self.selected().cloned() else {
+            return;
+        };
+
+        if !self.marked.remove(&item) {
+            self.marked.insert(item);
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        self.marked = self.items.iter().cloned().collect();
+    }
+
+    pub fn invert_marks(&mut self) {
+        self.marked = self
+            .items
+            .iter()
+            .filter(|item| !self.marked.contains(item))
+            .cloned()
+            .collect();
+    }
+
+    /// The marked items, in the order they're listed.
+    pub fn marked_items(&self) -> Vec<T> {
+        self.items
+            .iter()
+            .filter(|item| self.marked.contains(item))
+            .cloned()
+            .collect()
+    }
+}
+
 impl Widget for StatefulList<Id> {
     type AppData = CardCache;
 
@@ -265,10 +392,16 @@ impl Widget for StatefulList<Id> {
             .map(|i| {
                 let front = cache
                     .try_get_ref(*i)
-                    .map(|i| i.front_text().to_owned())
+                    .map(|i| front_display(&i))
                     .unwrap_or("----".to_string());
-                let lines = vec![Line::from(front)];
-                ListItem::new(lines).style(Style::default().fg(Color::Black).bg(Color::White))
+
+                if self.marked.contains(i) {
+                    let lines = vec![Line::from(format!("* {front}"))];
+                    ListItem::new(lines).style(Style::default().fg(Color::Black).bg(Color::Yellow))
+                } else {
+                    let lines = vec![Line::from(front)];
+                    ListItem::new(lines).style(Style::default().fg(Color::Black).bg(Color::White))
+                }
             })
             .collect();
 
@@ -287,6 +420,39 @@ impl Widget for StatefulList<Id> {
     }
 }
 
+impl Widget for StatefulList<String> {
+    type AppData = CardCache;
+
+    fn keyhandler(&mut self, _cache: &mut CardCache, key: crossterm::event::KeyEvent) {
+        match key.code {
+            crossterm::event::KeyCode::Up => self.previous(),
+            crossterm::event::KeyCode::Down => self.next(),
+            crossterm::event::KeyCode::Char('k') => self.previous(),
+            crossterm::event::KeyCode::Char('j') => self.next(),
+            _ => {}
+        }
+    }
+
+    fn render(&mut self, f: &mut Frame, _cache: &mut CardCache, area: Rect) {
+        let items: Vec<ListItem> = self
+            .items
+            .iter()
+            .map(|item| ListItem::new(vec![Line::from(item.as_str())]))
+            .collect();
+
+        let items = List::new(items)
+            .highlight_style(
+                Style::default()
+                    .bg(Color::LightGreen)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol(">> ");
+
+        let mut state = self.state.clone();
+        f.render_stateful_widget(items, area, &mut state);
+    }
+}
+
 pub fn _read_text_file<P: AsRef<Path>>(path: P) -> Option<String> {
     let path = path.as_ref();
     if !path.is_file() {
@@ -0,0 +1,618 @@
+use std::{iter::Peekable, time::Duration, vec::IntoIter};
+
+use serde::{Deserialize, Serialize};
+use speki_backend::{
+    cache::CardCache, categories::Category, common::current_time, filter::FilterUtil,
+    saved_card::SavedCard, Id,
+};
+use vedvaring::SingletonPersist;
+
+use crate::{popups::due_time, settings::Settings};
+
+use super::created_time;
+
+/// Shown next to the search, and when a query can't be parsed.
+pub const QUERY_HELP: &str = "tag:<tag> tag:a,b cat:<category> cat:a,b \
+suspended|resolved|pending|finished:<true/false> \
+recall<0.8 recall>80% stability>7d strength<12h \"some text\" \
+created-after|created-before|reviewed-after|reviewed-before: \
+modified-since|due-within:<2024-05-31 or 7d> \
+a or b, -term, not (a b), \
+dependencies:(...) anydependency:(...) dependents:(...) anydependent:(...)";
+
+/// A filter with the boolean logic and the nesting that `FilterUtil` can't express on its own.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Query {
+    Filter(FilterUtil),
+    All(Vec<Query>),
+    Any(Vec<Query>),
+    Not(Box<Query>),
+    /// Cards without dependencies match too, like with `FilterUtil::all_dependencies`.
+    AllDependencies(Box<Query>),
+    AnyDependency(Box<Query>),
+    /// Cards without dependents match too.
+    AllDependents(Box<Query>),
+    AnyDependent(Box<Query>),
+    Date(DateFilter),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DateField {
+    Created,
+    Reviewed,
+    Modified,
+    Due,
+}
+
+impl DateField {
+    /// As time since the unix epoch.
+    fn time(self, card: &SavedCard, settings: &Settings) -> Option<Duration> {
+        match self {
+            Self::Created => created_time(card),
+            Self::Reviewed => card.reviews().last().map(|review| review.timestamp),
+            Self::Modified => Some(card.last_modified()),
+            Self::Due => due_time(card, settings),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum When {
+    /// Days since the unix epoch, in UTC.
+    Date(u64),
+    /// That long ago, or from now for the due date. Kept relative so saved searches stay current.
+    Relative(Duration),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DateFilter {
+    pub field: DateField,
+    /// Before the time, otherwise at or after it.
+    pub before: bool,
+    pub when: When,
+}
+
+impl DateFilter {
+    fn keep_card(&self, card: &SavedCard, settings: &Settings) -> bool {
+        let Some(time) = self.field.time(card, settings) else {
+            return false;
+        };
+
+        let bound = match self.when {
+            When::Date(days) => Duration::from_secs(days * 86400),
+            When::Relative(duration) if self.field == DateField::Due => current_time() + duration,
+            When::Relative(duration) => current_time().saturating_sub(duration),
+        };
+
+        if self.before {
+            time < bound
+        } else {
+            time >= bound
+        }
+    }
+}
+
+impl Default for Query {
+    /// Matches every card.
+    fn default() -> Self {
+        Self::All(vec![])
+    }
+}
+
+impl From<FilterUtil> for Query {
+    fn from(filter: FilterUtil) -> Self {
+        Self::Filter(filter)
+    }
+}
+
+impl Query {
+    pub fn evaluate_cards(&self, cards: Vec<Id>, cache: &mut CardCache) -> Vec<Id> {
+        // The due dates depend on the thresholds.
+        let settings = Settings::load_or_default();
+        cards
+            .into_iter()
+            .filter(|card| self.keep_card(*card, cache, &settings))
+            .collect()
+    }
+
+    fn keep_card(&self, card: Id, cache: &mut CardCache, settings: &Settings) -> bool {
+        let keep =
+            |query: &Query, card: Id, cache: &mut CardCache| query.keep_card(card, cache, settings);
+
+        match self {
+            Self::Filter(filter) => filter.keep_card(card, cache),
+            Self::All(queries) => queries.iter().all(|query| keep(query, card, cache)),
+            Self::Any(queries) => queries.iter().any(|query| keep(query, card, cache)),
+            Self::Not(query) => !keep(query, card, cache),
+            Self::AllDependencies(query) => cache
+                .dependencies(card)
+                .into_iter()
+                .all(|card| keep(query, card, cache)),
+            Self::AnyDependency(query) => cache
+                .dependencies(card)
+                .into_iter()
+                .any(|card| keep(query, card, cache)),
+            Self::AllDependents(query) => cache
+                .dependents(card)
+                .into_iter()
+                .all(|card| keep(query, card, cache)),
+            Self::AnyDependent(query) => cache
+                .dependents(card)
+                .into_iter()
+                .any(|card| keep(query, card, cache)),
+            Self::Date(filter) => filter.keep_card(&cache.get_ref(card), settings),
+        }
+    }
+
+    /// Whether any part of the query filters on the date.
+    pub fn uses_date(&self, field: DateField) -> bool {
+        match self {
+            Self::Filter(_) => false,
+            Self::All(queries) | Self::Any(queries) => {
+                queries.iter().any(|query| query.uses_date(field))
+            }
+            Self::Not(query)
+            | Self::AllDependencies(query)
+            | Self::AnyDependency(query)
+            | Self::AllDependents(query)
+            | Self::AnyDependent(query) => query.uses_date(field),
+            Self::Date(filter) => filter.field == field,
+        }
+    }
+
+    /// No need to wrap a single query.
+    fn all(mut queries: Vec<Query>) -> Self {
+        if queries.len() == 1 {
+            queries.remove(0)
+        } else {
+            Self::All(queries)
+        }
+    }
+
+    fn any(mut queries: Vec<Query>) -> Self {
+        if queries.len() == 1 {
+            queries.remove(0)
+        } else {
+            Self::Any(queries)
+        }
+    }
+}
+
+/// A whitespace separated part of the query, with the quotes taken out.
+struct Term {
+    text: String,
+    /// Started with a quote, so it's text to search for even if it looks like `key:value`.
+    quoted: bool,
+}
+
+impl Term {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        !self.quoted && self.text.eq_ignore_ascii_case(keyword)
+    }
+}
+
+enum Token {
+    Open,
+    Close,
+    Term(Term),
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn end_term(tokens: &mut Vec<Token>, text: &mut String, quoted: &mut bool) {
+    if !text.is_empty() || *quoted {
+        tokens.push(Token::Term(Term {
+            text: std::mem::take(text),
+            quoted: *quoted,
+        }));
+    }
+
+    *quoted = false;
+}
+
+fn tokens(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut text = String::new();
+    let mut in_quotes = false;
+    let mut quoted = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => {
+                quoted |= text.is_empty() && !in_quotes;
+                in_quotes = !in_quotes;
+            }
+            '(' | ')' if !in_quotes => {
+                end_term(&mut tokens, &mut text, &mut quoted);
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            c if c.is_whitespace() && !in_quotes => end_term(&mut tokens, &mut text, &mut quoted),
+            c => text.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err("missing a closing quote".into());
+    }
+
+    end_term(&mut tokens, &mut text, &mut quoted);
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Is,
+    Below,
+    Above,
+}
+
+/// Splits `recall<0.8` into the key, the comparison and the value.
+fn split_term(term: &str) -> Option<(&str, Op, &str)> {
+    let pos = term.find([':', '<', '>'])?;
+    let op = match &term[pos..pos + 1] {
+        ":" => Op::Is,
+        "<" => Op::Below,
+        _ => Op::Above,
+    };
+
+    Some((&term[..pos], op, &term[pos + 1..]))
+}
+
+fn boolean(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        _ => Err(format!("'{value}' isn't true or false")),
+    }
+}
+
+/// Either a fraction like `0.8` or a percentage like `80%`.
+fn recall_rate(value: &str) -> Result<f32, String> {
+    let rate = match value.strip_suffix('%') {
+        Some(percent) => percent.parse::<f32>().map(|percent| percent / 100.),
+        None => value.parse::<f32>(),
+    }
+    .map_err(|_| format!("'{value}' isn't a recall rate"))?;
+
+    if !(0. ..=1.).contains(&rate) {
+        return Err(format!("'{value}' isn't between 0 and 1"));
+    }
+
+    Ok(rate)
+}
+
+/// A number with an `s`, `m`, `h`, `d` or `w` suffix, days if there's none.
+fn duration(value: &str) -> Result<Duration, String> {
+    let (number, secs) = match value.char_indices().last() {
+        Some((pos, 's')) => (&value[..pos], 1.),
+        Some((pos, 'm')) => (&value[..pos], 60.),
+        Some((pos, 'h')) => (&value[..pos], 3600.),
+        Some((pos, 'd')) => (&value[..pos], 86400.),
+        Some((pos, 'w')) => (&value[..pos], 604800.),
+        _ => (value, 86400.),
+    };
+
+    match number.parse::<f64>() {
+        Ok(number) if number >= 0. && number.is_finite() => {
+            Ok(Duration::from_secs_f64(number * secs))
+        }
+        _ => Err(format!("'{value}' isn't a duration like 7d or 12h")),
+    }
+}
+
+/// Days since the unix epoch of a date like `2024-05-31`, in UTC.
+fn date(value: &str) -> Option<u64> {
+    let mut parts = value.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // The days_from_civil algorithm, with the years starting in March so the leap day is last.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    u64::try_from(era * 146097 + day_of_era - 719468).ok()
+}
+
+fn when(value: &str) -> Result<When, String> {
+    match date(value) {
+        Some(days) => Ok(When::Date(days)),
+        None => duration(value)
+            .map(When::Relative)
+            .map_err(|_| format!("'{value}' isn't a date like 2024-05-31 or a duration like 7d")),
+    }
+}
+
+/// Which date the key is about, and whether it's before that date.
+fn date_key(key: &str) -> Option<(DateField, bool)> {
+    let key = match key {
+        "created-before" => (DateField::Created, true),
+        "created-after" => (DateField::Created, false),
+        "reviewed-before" => (DateField::Reviewed, true),
+        "reviewed-after" => (DateField::Reviewed, false),
+        "modified-before" => (DateField::Modified, true),
+        "modified-after" | "modified-since" => (DateField::Modified, false),
+        "due-before" | "due-within" => (DateField::Due, true),
+        "due-after" => (DateField::Due, false),
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Comma separated, a card in any of them matches.
+fn categories(value: &str) -> Result<Vec<Category>, String> {
+    value
+        .split(',')
+        .map(|category| {
+            category
+                .trim()
+                .parse::<Category>()
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+fn contains(text: &str) -> Query {
+    Query::Filter(FilterUtil {
+        contains: Some(text.to_string()),
+        ..Default::default()
+    })
+}
+
+fn key_filter(key: &str, op: Op, value: &str) -> Result<FilterUtil, String> {
+    let mut filter = FilterUtil::default();
+
+    match (key, op) {
+        ("tag", Op::Is) => filter.tags = value.split(',').map(|tag| tag.to_string()).collect(),
+        ("cat", Op::Is) => filter.allowed_categories = categories(value)?,
+        ("suspended", Op::Is) => filter.suspended = Some(boolean(value)?),
+        ("resolved", Op::Is) => filter.resolved = Some(boolean(value)?),
+        ("pending", Op::Is) => filter.pending = Some(boolean(value)?),
+        ("finished", Op::Is) => filter.finished = Some(boolean(value)?),
+        ("recall", Op::Below) => filter.max_recall_rate = Some(recall_rate(value)?),
+        ("recall", Op::Above) => filter.min_recall_rate = Some(recall_rate(value)?),
+        ("stability", Op::Below) => filter.max_stability = Some(duration(value)?),
+        ("stability", Op::Above) => filter.min_stability = Some(duration(value)?),
+        ("strength", Op::Below) => filter.max_strength = Some(duration(value)?),
+        ("strength", Op::Above) => filter.min_strength = Some(duration(value)?),
+        ("recall" | "stability" | "strength", Op::Is) => {
+            return Err(format!("use {key}<value or {key}>value"))
+        }
+        ("tag" | "cat" | "suspended" | "resolved" | "pending" | "finished", _) => {
+            return Err(format!("use {key}:value"))
+        }
+        _ => return Err(format!("unknown key '{key}'")),
+    }
+
+    Ok(filter)
+}
+
+/// The `dependencies:(...)` kind of terms, that match on the cards related to the card.
+fn relation(key: &str) -> Option<fn(Box<Query>) -> Query> {
+    match key {
+        "dependencies" => Some(Query::AllDependencies),
+        "anydependency" => Some(Query::AnyDependency),
+        "dependents" => Some(Query::AllDependents),
+        "anydependent" => Some(Query::AnyDependent),
+        _ => None,
+    }
+}
+
+/// Everything up to the closing parenthesis, the opening one is already taken.
+fn group(tokens: &mut Tokens) -> Result<Query, String> {
+    let query = any(tokens)?;
+
+    match tokens.next() {
+        Some(Token::Close) => Ok(query),
+        _ => Err("missing a closing parenthesis".into()),
+    }
+}
+
+fn term(text: &str, tokens: &mut Tokens) -> Result<Query, String> {
+    let Some((key, op, value)) = split_term(text) else {
+        return Ok(contains(text));
+    };
+
+    if let Some(relation) = relation(key) {
+        return match (op, value, tokens.next()) {
+            (Op::Is, "", Some(Token::Open)) => Ok(relation(Box::new(group(tokens)?))),
+            _ => Err(format!("'{text}': use {key}:(...)")),
+        };
+    }
+
+    if value.is_empty() {
+        return Err(format!("'{text}': missing a value"));
+    }
+
+    if let Some((field, before)) = date_key(key) {
+        return match op {
+            Op::Is => when(value)
+                .map(|when| {
+                    Query::Date(DateFilter {
+                        field,
+                        before,
+                        when,
+                    })
+                })
+                .map_err(|e| format!("'{text}': {e}")),
+            _ => Err(format!("'{text}': use {key}:value")),
+        };
+    }
+
+    key_filter(key, op, value)
+        .map(Query::Filter)
+        .map_err(|e| format!("'{text}': {e}"))
+}
+
+fn not(tokens: &mut Tokens) -> Result<Query, String> {
+    match tokens.next() {
+        Some(Token::Term(t)) if t.is_keyword("-") || t.is_keyword("not") => {
+            Ok(Query::Not(Box::new(not(tokens)?)))
+        }
+        Some(Token::Term(t)) if t.quoted => Ok(contains(&t.text)),
+        Some(Token::Term(t)) => match t.text.strip_prefix('-') {
+            Some(negated) => Ok(Query::Not(Box::new(term(negated, tokens)?))),
+            None => term(&t.text, tokens),
+        },
+        Some(Token::Open) => group(tokens),
+        Some(Token::Close) => Err("')' without a matching '('".into()),
+        None => Err("missing a search term at the end".into()),
+    }
+}
+
+/// Terms next to each other all have to match.
+fn all(tokens: &mut Tokens) -> Result<Query, String> {
+    let mut queries = vec![];
+
+    loop {
+        match tokens.peek() {
+            None | Some(Token::Close) => break,
+            Some(Token::Term(t)) if t.is_keyword("or") => break,
+            _ => queries.push(not(tokens)?),
+        }
+    }
+
+    if queries.is_empty() {
+        return Err("missing a search term next to 'or' or in '()'".into());
+    }
+
+    Ok(Query::all(queries))
+}
+
+fn any(tokens: &mut Tokens) -> Result<Query, String> {
+    let mut queries = vec![all(tokens)?];
+
+    while let Some(Token::Term(t)) = tokens.peek() {
+        if !t.is_keyword("or") {
+            break;
+        }
+
+        tokens.next();
+        queries.push(all(tokens)?);
+    }
+
+    Ok(Query::any(queries))
+}
+
+/// Parses a search like `tag:rust (recall<0.8 or -finished:true) "borrow checker"`. Terms next to
+/// each other all have to match, `or` binds looser than that, and `-` or `not` negates.
+pub fn parse_query(query: &str) -> Result<Query, String> {
+    let mut tokens = tokens(query)?.into_iter().peekable();
+
+    if tokens.peek().is_none() {
+        return Ok(Query::default());
+    }
+
+    let query = any(&mut tokens)?;
+
+    match tokens.next() {
+        None => Ok(query),
+        Some(_) => Err("')' without a matching '('".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(query: &Query) -> &FilterUtil {
+        match query {
+            Query::Filter(filter) => filter,
+            other => panic!("not a single filter: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn full_query() {
+        let query =
+            parse_query("tag:rust recall<0.8 stability>7d suspended:false \"borrow checker\"")
+                .unwrap();
+        let Query::All(terms) = query else {
+            panic!("not all of the terms");
+        };
+
+        assert_eq!(filter(&terms[0]).tags, vec!["rust".to_string()]);
+        assert_eq!(filter(&terms[1]).max_recall_rate, Some(0.8));
+        assert_eq!(
+            filter(&terms[2]).min_stability,
+            Some(Duration::from_secs(7 * 86400))
+        );
+        assert_eq!(filter(&terms[3]).suspended, Some(false));
+        assert_eq!(
+            filter(&terms[4]).contains.as_deref(),
+            Some("borrow checker")
+        );
+    }
+
+    #[test]
+    fn boolean_logic() {
+        let query = parse_query("a b or -(c or not d) dependencies:(recall>0.9)").unwrap();
+        let Query::Any(any) = query else {
+            panic!("'or' should bind loosest");
+        };
+
+        assert!(matches!(&any[0], Query::All(all) if all.len() == 2));
+        let Query::All(all) = &any[1] else {
+            panic!("not all of the terms");
+        };
+        assert!(matches!(&all[0], Query::Not(not) if matches!(**not, Query::Any(_))));
+        assert!(matches!(&all[1], Query::AllDependencies(_)));
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date("1970-01-01"), Some(0));
+        assert_eq!(date("2024-02-29"), Some(19782));
+        assert_eq!(date("2024-13-01"), None);
+
+        let query = parse_query("created-after:2024-01-01 due-within:7d").unwrap();
+        let Query::All(terms) = query else {
+            panic!("not all of the terms");
+        };
+        assert!(matches!(
+            &terms[0],
+            Query::Date(DateFilter {
+                field: DateField::Created,
+                before: false,
+                when: When::Date(19723),
+            })
+        ));
+        assert!(matches!(
+            &terms[1],
+            Query::Date(DateFilter {
+                field: DateField::Due,
+                before: true,
+                when: When::Relative(_),
+            })
+        ));
+        assert!(parse_query("reviewed-before<7d").is_err());
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(recall_rate("80%"), Ok(0.8));
+        assert!(recall_rate("1.5").is_err());
+        assert_eq!(duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(duration("2"), Ok(Duration::from_secs(2 * 86400)));
+        assert!(duration("7x").is_err());
+    }
+
+    #[test]
+    fn bad_input() {
+        assert!(parse_query("foo:bar").unwrap_err().contains("unknown key"));
+        assert!(parse_query("recall:0.8").is_err());
+        assert!(parse_query("\"unclosed").is_err());
+        assert!(parse_query("tag:").is_err());
+        assert!(parse_query("(a or b").is_err());
+        assert!(parse_query("a)").is_err());
+        assert!(parse_query("a or").is_err());
+        assert!(parse_query("dependents:foo").is_err());
+    }
+}
@@ -41,6 +41,13 @@ where
         }
     }
 
+    /// Leaves an option out of the list.
+    pub fn without(mut self, item: T) -> Self {
+        let item = item.to_string();
+        self.list.items.retain(|other| *other != item);
+        self
+    }
+
     pub fn len(&self) -> usize {
         self.list.items.len()
     }
@@ -35,6 +35,9 @@ impl FieldsConstructible for FilterUtil {
             allowed_categories: *parse_value(fields, "allowed_categories")
                 .downcast()
                 .unwrap(),
+            excluded_categories: *parse_value(fields, "excluded_categories")
+                .downcast()
+                .unwrap(),
             ..Default::default()
         }
     }
@@ -54,6 +57,7 @@ impl FieldsConstructible for FilterUtil {
             create_field!("max_recall_rate", Option<f32>),
             create_field!("min_recall_rate", Option<f32>),
             create_field!("allowed_categories", CategoryThing),
+            create_field!("excluded_categories", CategoryThing),
         ])
     }
 }
@@ -223,11 +227,12 @@ pub mod r#macro {
                     if input.trim().is_empty() {
                         Ok(Box::<Vec<Category>>::default())
                     } else {
-                        Ok(input
-                            .trim()
-                            .parse::<Category>()
-                            .map_err(|e| e.to_string())
-                            .and_then(|x| Ok(Box::new(vec![x])))?)
+                        // Comma separated, for when there's more than one.
+                        let categories: Result<Vec<Category>, String> = input
+                            .split(',')
+                            .map(|x| x.trim().parse::<Category>().map_err(|e| e.to_string()))
+                            .collect();
+                        Ok(Box::new(categories?))
                     }
                 }),
                 value: TextArea::default(),